@@ -0,0 +1 @@
+ALTER TABLE members_data ADD COLUMN access_token_expires_at TIMESTAMPTZ;
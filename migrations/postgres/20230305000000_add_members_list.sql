@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS members_list (
+    discord_user_id TEXT PRIMARY KEY,
+    display_name TEXT,
+    twitter JSONB NOT NULL,
+    github JSONB NOT NULL,
+    role_name TEXT,
+    role_color TEXT,
+    synced_at TIMESTAMPTZ NOT NULL
+);
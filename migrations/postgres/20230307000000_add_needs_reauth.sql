@@ -0,0 +1,2 @@
+ALTER TABLE members_data ADD COLUMN needs_reauth_since TIMESTAMPTZ;
+ALTER TABLE members_list ADD COLUMN needs_reauth_since TIMESTAMPTZ;
@@ -0,0 +1 @@
+ALTER TABLE oauth2_data ADD COLUMN requested_by TEXT;
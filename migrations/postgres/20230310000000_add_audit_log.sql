@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS members_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    discord_user_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    data_before JSONB,
+    data_after JSONB
+);
+
+CREATE INDEX IF NOT EXISTS members_audit_log_discord_user_id ON members_audit_log (discord_user_id);
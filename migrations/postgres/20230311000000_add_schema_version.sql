@@ -0,0 +1,8 @@
+ALTER TABLE members_data ADD COLUMN schema_version BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS members_data_migrations (
+    version BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL,
+    migrated_members BIGINT NOT NULL
+);
@@ -0,0 +1,6 @@
+ALTER TABLE members_data ADD COLUMN registered_at TIMESTAMPTZ;
+ALTER TABLE members_data ADD COLUMN updated_at TIMESTAMPTZ;
+ALTER TABLE members_data ADD COLUMN last_token_refresh_at TIMESTAMPTZ;
+ALTER TABLE members_list ADD COLUMN registered_at TIMESTAMPTZ;
+ALTER TABLE members_list ADD COLUMN updated_at TIMESTAMPTZ;
+ALTER TABLE members_list ADD COLUMN last_token_refresh_at TIMESTAMPTZ;
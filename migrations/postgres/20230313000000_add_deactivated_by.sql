@@ -0,0 +1 @@
+ALTER TABLE members_data ADD COLUMN deactivated_by TEXT;
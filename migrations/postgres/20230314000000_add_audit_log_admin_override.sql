@@ -0,0 +1,3 @@
+ALTER TABLE members_audit_log ADD COLUMN admin_override BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE members_audit_log SET admin_override = TRUE WHERE actor LIKE 'moderator:%';
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS members_data (
+    discord_user_id TEXT PRIMARY KEY NOT NULL,
+    display_name TEXT,
+    access_token TEXT NOT NULL,
+    refresh_token TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS oauth2_data (
+    csrf_token TEXT PRIMARY KEY NOT NULL,
+    pkce_verifier TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
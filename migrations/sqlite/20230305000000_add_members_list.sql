@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS members_list (
+    discord_user_id TEXT PRIMARY KEY NOT NULL,
+    display_name TEXT,
+    twitter TEXT NOT NULL,
+    github TEXT NOT NULL,
+    role_name TEXT,
+    role_color TEXT,
+    synced_at TEXT NOT NULL
+);
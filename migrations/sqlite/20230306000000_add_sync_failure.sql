@@ -0,0 +1,4 @@
+ALTER TABLE members_data ADD COLUMN sync_failure_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE members_data ADD COLUMN sync_last_error TEXT;
+ALTER TABLE members_data ADD COLUMN sync_last_failed_at TEXT;
+ALTER TABLE members_list ADD COLUMN stale INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE members_data ADD COLUMN inactive_since TEXT;
+ALTER TABLE members_list ADD COLUMN inactive_since TEXT;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS members_audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    discord_user_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    recorded_at TEXT NOT NULL,
+    data_before TEXT,
+    data_after TEXT
+);
+
+CREATE INDEX IF NOT EXISTS members_audit_log_discord_user_id ON members_audit_log (discord_user_id);
@@ -0,0 +1,8 @@
+ALTER TABLE members_data ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS members_data_migrations (
+    version INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    applied_at TEXT NOT NULL,
+    migrated_members INTEGER NOT NULL
+);
@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context as _};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use oauth2::url::Url;
+
+const DEFAULT_CSRF_TOKEN_TTL_SECS: i64 = 60 * 60 * 24;
+const DEFAULT_CSRF_TOKEN_SWEEP_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_MEMBER_SYNC_INTERVAL_SECS: u64 = 10 * 60;
+const DEFAULT_MEMBER_SYNC_CONCURRENCY: usize = 4;
+/// 5 requests a second, well below the global rate limit of 50.
+const DEFAULT_MEMBER_SYNC_REQUEST_INTERVAL_MS: u64 = 200;
+const DEFAULT_DISPLAY_NAME_MAX_LENGTH: usize = 32;
+const DEFAULT_POSTGRES_MAX_CONNECTIONS: u32 = 10;
+const DEFAULT_HTTP_PORT: u16 = 8080;
+/// AES-256-GCM needs 256-bit keys.
+const ENCRYPTION_KEY_LEN: usize = 32;
+
+/// Every setting of the application, validated as a whole at startup.
+///
+/// Each key is read from its environment variable, falling back to the TOML file given with
+/// `CONFIG_FILE` (e.g. `DISCORD_TOKEN` or `token` in the `[discord]` table).
+pub(crate) struct Config {
+    pub(crate) discord: DiscordConfig,
+    pub(crate) oauth2: OAuth2Config,
+    pub(crate) storage: StorageConfig,
+    pub(crate) token_encryption: TokenEncryptionConfig,
+    pub(crate) archive: ArchiveConfig,
+    pub(crate) http: HttpConfig,
+    pub(crate) jobs: JobsConfig,
+    pub(crate) display_name: DisplayNameConfig,
+}
+
+pub(crate) struct DiscordConfig {
+    pub(crate) token: String,
+    pub(crate) guild_id: u64,
+    pub(crate) text_commands_enabled: bool,
+    /// Needs the privileged server members intent, enabled in the developer portal as well.
+    /// Without it, members who join or leave are caught up with only when the bot starts.
+    pub(crate) member_events_enabled: bool,
+    /// Members need one of them to register. Empty means no role is required.
+    pub(crate) required_role_ids: Vec<u64>,
+    /// Members with one of them can run the admin commands, besides the owners of the bot.
+    pub(crate) admin_role_ids: Vec<u64>,
+    /// Channel which the audit log is mirrored to.
+    pub(crate) audit_log_channel_id: Option<u64>,
+}
+
+pub(crate) struct OAuth2Config {
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    pub(crate) redirect_url: String,
+    pub(crate) csrf_token_ttl_secs: i64,
+}
+
+pub(crate) struct StorageConfig {
+    pub(crate) backend: StorageBackend,
+    pub(crate) firestore: FirestoreConfig,
+    /// Required with the `sqlite` backend.
+    pub(crate) sqlite_database_url: String,
+    /// Required with the `postgres` backend.
+    pub(crate) postgres_database_url: String,
+    pub(crate) postgres_max_connections: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StorageBackend {
+    Firestore,
+    Memory,
+    Sqlite,
+    Postgres,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "firestore" => Ok(Self::Firestore),
+            "memory" => Ok(Self::Memory),
+            "sqlite" => Ok(Self::Sqlite),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(format!("unknown storage backend '{s}'")),
+        }
+    }
+}
+
+pub(crate) struct FirestoreConfig {
+    /// Required with the `firestore` backend.
+    pub(crate) project_id: String,
+    pub(crate) members_collection: String,
+    pub(crate) snapshots_collection: String,
+    pub(crate) audit_log_collection: String,
+    pub(crate) migrations_collection: String,
+    pub(crate) oauth2_collection: String,
+}
+
+pub(crate) struct TokenEncryptionConfig {
+    /// Decoded keys by id. Tokens are stored as plaintext when it is empty.
+    pub(crate) keys: HashMap<String, Vec<u8>>,
+    /// The first listed key unless set explicitly.
+    pub(crate) primary_key_id: String,
+}
+
+pub(crate) struct ArchiveConfig {
+    /// Decoded key which the tokens in exported archives are encrypted with, plaintext if unset.
+    pub(crate) encryption_key: Option<Vec<u8>>,
+}
+
+pub(crate) struct HttpConfig {
+    pub(crate) bind_address: IpAddr,
+    pub(crate) port: u16,
+    /// The admin api is disabled unless it is set.
+    pub(crate) admin_api_token: Option<String>,
+}
+
+pub(crate) struct JobsConfig {
+    pub(crate) csrf_token_sweep_interval_secs: u64,
+    pub(crate) member_sync_interval_secs: u64,
+    pub(crate) member_sync_concurrency: usize,
+    /// Minimum gap between the members fetched by the background jobs.
+    pub(crate) member_sync_request_interval_ms: u64,
+}
+
+pub(crate) struct DisplayNameConfig {
+    pub(crate) max_length: usize,
+    /// Every character of it is forbidden.
+    pub(crate) forbidden_characters: String,
+    pub(crate) banned_words: Vec<String>,
+}
+
+/// Which settings a command needs. The keys outside of it are still validated if they are set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Scope {
+    /// Everything, including the discord and oauth2 secrets.
+    Full,
+    /// Only the storage and the encryption keys, for the commands which never reach discord.
+    Storage,
+}
+
+impl Config {
+    /// Loads the config, reporting every missing or invalid key at once.
+    pub(crate) fn load(scope: Scope) -> anyhow::Result<Self> {
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => read_file(Path::new(&path))?,
+            _ => toml::Table::new(),
+        };
+        let mut loader = Loader {
+            file,
+            errors: Vec::new(),
+        };
+        let full = scope == Scope::Full;
+
+        let config = Self {
+            discord: DiscordConfig {
+                token: loader.required_if(full, "DISCORD_TOKEN", "discord.token"),
+                guild_id: loader.required_if(full, "DISCORD_GUILD_ID", "discord.guild_id"),
+                text_commands_enabled: loader.or(
+                    "DISCORD_TEXT_COMMANDS_ENABLED",
+                    "discord.text_commands_enabled",
+                    true,
+                ),
+                member_events_enabled: loader.or(
+                    "DISCORD_MEMBER_EVENTS_ENABLED",
+                    "discord.member_events_enabled",
+                    true,
+                ),
+                required_role_ids: loader.list("REQUIRED_ROLE_IDS", "discord.required_role_ids"),
+                admin_role_ids: loader.list("ADMIN_ROLE_IDS", "discord.admin_role_ids"),
+                audit_log_channel_id: loader
+                    .optional("AUDIT_LOG_CHANNEL_ID", "discord.audit_log_channel_id"),
+            },
+            oauth2: OAuth2Config {
+                client_id: loader.required_if(full, "OAUTH2_CLIENT_ID", "oauth2.client_id"),
+                client_secret: loader.required_if(
+                    full,
+                    "OAUTH2_CLIENT_SECRET",
+                    "oauth2.client_secret",
+                ),
+                // parsed only to validate it up front.
+                redirect_url: if full {
+                    loader.required_with::<Url>("OAUTH2_REDIRECT_URL", "oauth2.redirect_url")
+                } else {
+                    loader.optional::<Url>("OAUTH2_REDIRECT_URL", "oauth2.redirect_url")
+                }
+                .map(String::from)
+                .unwrap_or_default(),
+                csrf_token_ttl_secs: loader.positive(
+                    "CSRF_TOKEN_TTL_SECS",
+                    "oauth2.csrf_token_ttl_secs",
+                    DEFAULT_CSRF_TOKEN_TTL_SECS,
+                ),
+            },
+            storage: storage_config(&mut loader),
+            token_encryption: token_encryption_config(&mut loader),
+            archive: archive_config(&mut loader),
+            http: HttpConfig {
+                bind_address: loader.or(
+                    "HTTP_BIND_ADDRESS",
+                    "http.bind_address",
+                    IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                ),
+                port: loader.or("PORT", "http.port", DEFAULT_HTTP_PORT),
+                admin_api_token: loader.optional("ADMIN_API_TOKEN", "http.admin_api_token"),
+            },
+            jobs: JobsConfig {
+                csrf_token_sweep_interval_secs: loader.positive(
+                    "CSRF_TOKEN_SWEEP_INTERVAL_SECS",
+                    "jobs.csrf_token_sweep_interval_secs",
+                    DEFAULT_CSRF_TOKEN_SWEEP_INTERVAL_SECS,
+                ),
+                member_sync_interval_secs: loader.positive(
+                    "MEMBER_SYNC_INTERVAL_SECS",
+                    "jobs.member_sync_interval_secs",
+                    DEFAULT_MEMBER_SYNC_INTERVAL_SECS,
+                ),
+                member_sync_concurrency: loader.positive(
+                    "MEMBER_SYNC_CONCURRENCY",
+                    "jobs.member_sync_concurrency",
+                    DEFAULT_MEMBER_SYNC_CONCURRENCY,
+                ),
+                member_sync_request_interval_ms: loader.positive(
+                    "MEMBER_SYNC_REQUEST_INTERVAL_MS",
+                    "jobs.member_sync_request_interval_ms",
+                    DEFAULT_MEMBER_SYNC_REQUEST_INTERVAL_MS,
+                ),
+            },
+            display_name: DisplayNameConfig {
+                max_length: loader.positive(
+                    "DISPLAY_NAME_MAX_LENGTH",
+                    "display_name.max_length",
+                    DEFAULT_DISPLAY_NAME_MAX_LENGTH,
+                ),
+                forbidden_characters: loader
+                    .optional(
+                        "DISPLAY_NAME_FORBIDDEN_CHARACTERS",
+                        "display_name.forbidden_characters",
+                    )
+                    .unwrap_or_default(),
+                banned_words: loader.list("DISPLAY_NAME_BANNED_WORDS", "display_name.banned_words"),
+            },
+        };
+
+        if !loader.errors.is_empty() {
+            let errors = loader.errors.join("\n  - ");
+            tracing::error!("invalid configuration:\n  - {}", errors);
+            bail!("invalid configuration:\n  - {errors}");
+        }
+
+        Ok(config)
+    }
+}
+
+fn storage_config(loader: &mut Loader) -> StorageConfig {
+    let backend = loader.or(
+        "STORAGE_BACKEND",
+        "storage.backend",
+        StorageBackend::Firestore,
+    );
+
+    StorageConfig {
+        backend,
+        firestore: FirestoreConfig {
+            project_id: loader.required_if(
+                backend == StorageBackend::Firestore,
+                "GOOGLE_PROJECT_ID",
+                "storage.firestore.project_id",
+            ),
+            members_collection: loader.or(
+                "FIRESTORE_MEMBERS_COLLECTION",
+                "storage.firestore.members_collection",
+                "members_data".to_owned(),
+            ),
+            snapshots_collection: loader.or(
+                "FIRESTORE_SNAPSHOTS_COLLECTION",
+                "storage.firestore.snapshots_collection",
+                "members_list".to_owned(),
+            ),
+            audit_log_collection: loader.or(
+                "FIRESTORE_AUDIT_LOG_COLLECTION",
+                "storage.firestore.audit_log_collection",
+                "members_audit_log".to_owned(),
+            ),
+            migrations_collection: loader.or(
+                "FIRESTORE_MIGRATIONS_COLLECTION",
+                "storage.firestore.migrations_collection",
+                "members_migrations".to_owned(),
+            ),
+            oauth2_collection: loader.or(
+                "FIRESTORE_OAUTH2_COLLECTION",
+                "storage.firestore.oauth2_collection",
+                "oauth2_data".to_owned(),
+            ),
+        },
+        sqlite_database_url: loader.required_if(
+            backend == StorageBackend::Sqlite,
+            "SQLITE_DATABASE_URL",
+            "storage.sqlite.database_url",
+        ),
+        postgres_database_url: loader.required_if(
+            backend == StorageBackend::Postgres,
+            "POSTGRES_DATABASE_URL",
+            "storage.postgres.database_url",
+        ),
+        postgres_max_connections: loader.positive(
+            "POSTGRES_MAX_CONNECTIONS",
+            "storage.postgres.max_connections",
+            DEFAULT_POSTGRES_MAX_CONNECTIONS,
+        ),
+    }
+}
+
+/// `TOKEN_ENCRYPTION_KEYS` lists `key_id=base64_key` entries.
+fn token_encryption_config(loader: &mut Loader) -> TokenEncryptionConfig {
+    let mut keys = HashMap::new();
+    let mut first_key_id = None;
+    for entry in loader.list::<String>("TOKEN_ENCRYPTION_KEYS", "token_encryption.keys") {
+        let Some((key_id, key)) = entry.split_once('=') else {
+            loader.errors.push(
+                "TOKEN_ENCRYPTION_KEYS (token_encryption.keys) has an entry without '='".to_owned(),
+            );
+            continue;
+        };
+        match STANDARD.decode(key) {
+            Ok(key) if key.len() == ENCRYPTION_KEY_LEN && !key_id.contains(':') => {
+                first_key_id.get_or_insert_with(|| key_id.to_owned());
+                keys.insert(key_id.to_owned(), key);
+            }
+            _ => loader.errors.push(format!(
+                "encryption key '{key_id}' is invalid, expected a base64 encoded 256-bit key without ':' in its id"
+            )),
+        }
+    }
+
+    let primary_key_id = loader
+        .optional::<String>(
+            "TOKEN_ENCRYPTION_PRIMARY_KEY_ID",
+            "token_encryption.primary_key_id",
+        )
+        .or(first_key_id)
+        .unwrap_or_default();
+    if !primary_key_id.is_empty() && !keys.contains_key(&primary_key_id) {
+        loader.errors.push(format!(
+            "TOKEN_ENCRYPTION_PRIMARY_KEY_ID (token_encryption.primary_key_id) '{primary_key_id}' is not in the encryption keys"
+        ));
+    }
+
+    TokenEncryptionConfig {
+        keys,
+        primary_key_id,
+    }
+}
+
+/// `ARCHIVE_ENCRYPTION_KEY` is a base64 encoded 256-bit key.
+fn archive_config(loader: &mut Loader) -> ArchiveConfig {
+    let encryption_key = loader
+        .optional::<String>("ARCHIVE_ENCRYPTION_KEY", "archive.encryption_key")
+        .and_then(|key| match STANDARD.decode(key) {
+            Ok(key) if key.len() == ENCRYPTION_KEY_LEN => Some(key),
+            _ => {
+                loader.errors.push(
+                    "ARCHIVE_ENCRYPTION_KEY (archive.encryption_key) is invalid, expected a base64 encoded 256-bit key"
+                        .to_owned(),
+                );
+                None
+            }
+        });
+
+    ArchiveConfig { encryption_key }
+}
+
+fn read_file(path: &Path) -> anyhow::Result<toml::Table> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read config file '{}'", path.display()))
+        .inspect_err(|err| tracing::error!("{}", err))?;
+
+    toml::from_str(&content)
+        .with_context(|| format!("could not parse config file '{}'", path.display()))
+        .inspect_err(|err| tracing::error!("{:#}", err))
+}
+
+/// Reads keys from the environment and the file, collecting the errors instead of returning early.
+struct Loader {
+    file: toml::Table,
+    errors: Vec<String>,
+}
+
+impl Loader {
+    /// Empty environment variables are treated as unset, as `.env.example` lists every key.
+    fn raw(&self, env_key: &str, file_key: &str) -> Option<String> {
+        match std::env::var(env_key) {
+            Ok(value) if !value.is_empty() => Some(value),
+            _ => lookup(&self.file, file_key).map(toml_to_string),
+        }
+    }
+
+    fn optional<T>(&mut self, env_key: &str, file_key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let raw = self.raw(env_key, file_key)?;
+        match raw.trim().parse() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.errors
+                    .push(format!("{env_key} ({file_key}) is invalid: {err}"));
+                None
+            }
+        }
+    }
+
+    fn or<T>(&mut self, env_key: &str, file_key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.optional(env_key, file_key).unwrap_or(default)
+    }
+
+    fn required_with<T>(&mut self, env_key: &str, file_key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if self.raw(env_key, file_key).is_none() {
+            self.errors
+                .push(format!("{env_key} ({file_key}) is missing"));
+            return None;
+        }
+
+        self.optional(env_key, file_key)
+    }
+
+    fn required<T>(&mut self, env_key: &str, file_key: &str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: Display,
+    {
+        self.required_with(env_key, file_key).unwrap_or_default()
+    }
+
+    fn required_if<T>(&mut self, condition: bool, env_key: &str, file_key: &str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: Display,
+    {
+        if condition {
+            self.required(env_key, file_key)
+        } else {
+            self.optional(env_key, file_key).unwrap_or_default()
+        }
+    }
+
+    fn positive<T>(&mut self, env_key: &str, file_key: &str, default: T) -> T
+    where
+        T: FromStr + Default + PartialOrd,
+        T::Err: Display,
+    {
+        let value = self.or(env_key, file_key, default);
+        if value <= T::default() {
+            self.errors
+                .push(format!("{env_key} ({file_key}) must be greater than 0"));
+        }
+
+        value
+    }
+
+    /// Entries are separated by commas, or given as an array in the file so that they can
+    /// contain commas themselves.
+    fn list<T>(&mut self, env_key: &str, file_key: &str) -> Vec<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let entries = match std::env::var(env_key) {
+            Ok(value) if !value.is_empty() => split_entries(&value),
+            _ => match lookup(&self.file, file_key) {
+                Some(toml::Value::Array(values)) => values.iter().map(toml_to_string).collect(),
+                Some(value) => split_entries(&toml_to_string(value)),
+                None => return Vec::new(),
+            },
+        };
+
+        entries
+            .iter()
+            .map(|entry| entry.trim())
+            .filter(|x| !x.is_empty())
+            .filter_map(|entry| match entry.parse() {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    self.errors.push(format!(
+                        "{env_key} ({file_key}) has an invalid entry '{entry}': {err}"
+                    ));
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Looks up a dotted key such as `storage.firestore.project_id`.
+fn lookup<'a>(table: &'a toml::Table, file_key: &str) -> Option<&'a toml::Value> {
+    match file_key.split_once('.') {
+        Some((section, rest)) => match table.get(section)? {
+            toml::Value::Table(inner) => lookup(inner, rest),
+            _ => None,
+        },
+        None => table.get(file_key),
+    }
+}
+
+fn split_entries(value: &str) -> Vec<String> {
+    value.split(',').map(str::to_owned).collect()
+}
+
+fn toml_to_string(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::panic_in_result_fn)]
+    fn arrays_in_the_file_keep_commas_in_their_entries() -> anyhow::Result<()> {
+        let mut loader = Loader {
+            file: toml::from_str(
+                r#"
+                [display_name]
+                banned_words = ["foo, bar", "baz"]
+                [discord]
+                admin_role_ids = "1, 2"
+                "#,
+            )?,
+            errors: Vec::new(),
+        };
+
+        let banned_words: Vec<String> = loader.list(
+            "TEST_DISPLAY_NAME_BANNED_WORDS",
+            "display_name.banned_words",
+        );
+        let admin_role_ids: Vec<u64> = loader.list("TEST_ADMIN_ROLE_IDS", "discord.admin_role_ids");
+
+        assert_eq!(banned_words, ["foo, bar", "baz"]);
+        assert_eq!(admin_role_ids, [1, 2]);
+        assert!(loader.errors.is_empty());
+        Ok(())
+    }
+}
@@ -1,2 +1,4 @@
+pub(crate) mod cli;
 pub(crate) mod discord;
 pub(crate) mod http;
+pub(crate) mod job;
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context as _};
+use clap::{Args, Parser, Subcommand};
+use tokio::fs::File;
+use tokio::io::{self, AsyncBufRead, AsyncWrite, BufReader, BufWriter};
+
+use crate::config::{Config, Scope};
+use crate::controller::discord::start_discord_bot;
+use crate::controller::http::start_http_server;
+use crate::controller::job::{spawn_csrf_token_sweeper, spawn_member_synchronizer};
+use crate::infra::repository::migration;
+use crate::usecase::audit::Actor;
+use crate::usecase::{
+    get_archive_usecase, get_repositories, get_usecases, DynArchiveUseCase, DynUseCaseContainer,
+};
+
+/// Serves the members database, or runs one of the maintenance tasks and exits.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    /// `serve` when omitted.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Starts the HTTP server and the discord bot.
+    Serve(ServeArgs),
+    /// Writes every record to a JSON Lines archive, which can be imported into any storage backend.
+    Export {
+        /// Written to stdout when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Restores an archive written by `export`, overwriting the records which already exist.
+    Import {
+        /// Read from stdin when omitted.
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+    },
+    /// Refreshes the access-token of every member.
+    RefreshAll,
+    /// Deletes the expired csrf-tokens.
+    SweepCsrf,
+    /// Saves every token again, so that they get encrypted with the current primary key.
+    ReencryptTokens,
+    /// Upgrades the stored members to the current schema, running the pending data migrations.
+    Migrate,
+    /// Validates the configuration without connecting to anything.
+    CheckConfig,
+}
+
+#[derive(Debug, Default, Args)]
+struct ServeArgs {
+    /// Starts only the HTTP server.
+    #[arg(long, conflicts_with = "bot_only")]
+    http_only: bool,
+    /// Starts only the discord bot.
+    #[arg(long)]
+    bot_only: bool,
+    /// Leaves the background jobs to another instance, or to a scheduler running `sweep-csrf`.
+    #[arg(long)]
+    no_jobs: bool,
+}
+
+impl Cli {
+    /// The commands which only move data around can run without the discord and oauth2 secrets.
+    pub(crate) fn config_scope(&self) -> Scope {
+        match self.command {
+            Some(Command::Export { .. } | Command::Import { .. } | Command::Migrate) => {
+                Scope::Storage
+            }
+            _ => Scope::Full,
+        }
+    }
+
+    pub(crate) async fn run(self, config: Config) -> anyhow::Result<()> {
+        let command = self
+            .command
+            .unwrap_or_else(|| Command::Serve(ServeArgs::default()));
+
+        // loading the config has already validated it.
+        if let Command::CheckConfig = command {
+            tracing::info!("configuration is valid");
+            return Ok(());
+        }
+
+        // run once before deploying, instead of by every instance on startup.
+        if let Command::Migrate = command {
+            let (members_repository, _) = get_repositories(&config).await?;
+            let applied = migration::run_migrations(&members_repository).await?;
+            tracing::info!("applied {} data migrations", applied);
+            return Ok(());
+        }
+
+        match command {
+            Command::Export { output } => {
+                let archive = get_archive_usecase(&config).await?;
+                return export(&archive, output.as_deref()).await;
+            }
+            Command::Import { input } => {
+                let archive = get_archive_usecase(&config).await?;
+                return import(&archive, input.as_deref()).await;
+            }
+            _ => {}
+        }
+
+        let usecases = get_usecases(&config).await?;
+        match command {
+            Command::Serve(args) => serve(usecases, &config, &args).await,
+            Command::RefreshAll => refresh_all(&usecases).await,
+            Command::SweepCsrf => usecases
+                .oauth2
+                .sweep_expired_csrf_tokens()
+                .await
+                .map(|_| ()),
+            Command::ReencryptTokens => usecases.oauth2.reencrypt_tokens().await.map(|_| ()),
+            Command::Export { .. }
+            | Command::Import { .. }
+            | Command::Migrate
+            | Command::CheckConfig => Ok(()),
+        }
+    }
+}
+
+async fn serve(
+    usecases: Arc<DynUseCaseContainer>,
+    config: &Config,
+    args: &ServeArgs,
+) -> anyhow::Result<()> {
+    if !args.no_jobs {
+        spawn_csrf_token_sweeper(Arc::clone(&usecases), &config.jobs)?;
+        spawn_member_synchronizer(Arc::clone(&usecases), &config.jobs)?;
+    }
+
+    if args.http_only {
+        return start_http_server(usecases, &config.http).await;
+    }
+    if args.bot_only {
+        return start_discord_bot(usecases, &config.discord).await;
+    }
+
+    tokio::try_join!(
+        start_http_server(Arc::clone(&usecases), &config.http),
+        start_discord_bot(usecases, &config.discord),
+    )
+    .map(|_| ())
+}
+
+async fn export(archive: &DynArchiveUseCase, output: Option<&Path>) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(open_output(output).await?);
+    archive.export(&mut writer).await.map(|_| ())
+}
+
+async fn import(archive: &DynArchiveUseCase, input: Option<&Path>) -> anyhow::Result<()> {
+    let mut reader = open_input(input).await?;
+    archive.import(&mut reader).await.map(|_| ())
+}
+
+/// Fails when any token could not be refreshed, so that schedulers notice it.
+async fn refresh_all(usecases: &DynUseCaseContainer) -> anyhow::Result<()> {
+    let summary = usecases.oauth2.refresh_all_tokens(&Actor::System).await?;
+    if summary.failed > 0 {
+        bail!("could not refresh tokens of {} members", summary.failed);
+    }
+
+    Ok(())
+}
+
+async fn open_output(path: Option<&Path>) -> anyhow::Result<Box<dyn AsyncWrite + Unpin + Send>> {
+    Ok(match path {
+        Some(file_path) => Box::new(
+            File::create(file_path)
+                .await
+                .with_context(|| format!("could not create '{}'", file_path.display()))?,
+        ),
+        None => Box::new(io::stdout()),
+    })
+}
+
+async fn open_input(path: Option<&Path>) -> anyhow::Result<Box<dyn AsyncBufRead + Unpin + Send>> {
+    Ok(match path {
+        Some(file_path) => {
+            let file = File::open(file_path)
+                .await
+                .with_context(|| format!("could not open '{}'", file_path.display()))?;
+            Box::new(BufReader::new(file))
+        }
+        None => Box::new(BufReader::new(io::stdin())),
+    })
+}
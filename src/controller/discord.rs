@@ -1,46 +1,82 @@
+use std::collections::HashSet;
 use std::future::Future;
 use std::sync::Arc;
 
 use anyhow::Context as _;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
 use serenity::framework::StandardFramework;
 use serenity::http::Http;
+use serenity::model::channel::Message;
 use serenity::model::gateway::GatewayIntents;
+use serenity::model::id::UserId;
 use serenity::Client;
 use tokio::signal;
 
-use crate::usecase::firebase::FirebaseUseCaseContainer;
-use crate::util::safe_env;
+use crate::config::DiscordConfig;
+use crate::infra::repository::RepositoryError;
+use crate::usecase::DynUseCaseContainer;
 
+mod admin;
 mod displayname;
 mod hook;
+mod link;
+mod profile;
+mod slash;
+mod status;
+mod unlink;
 
-#[tracing::instrument(skip(usecases))]
+/// Commands are plain functions generated by serenity's macros and cannot be generic,
+/// so the bot works on the trait-object container which covers every repository backend.
+#[tracing::instrument(skip(usecases, config))]
 pub(crate) async fn start_discord_bot(
-    usecases: Arc<FirebaseUseCaseContainer>,
+    usecases: Arc<DynUseCaseContainer>,
+    config: &DiscordConfig,
 ) -> anyhow::Result<()> {
-    let token = safe_env("DISCORD_TOKEN")?;
-    let http = Http::new(&token);
+    let http = Http::new(&config.token);
 
     let bot_id = http.get_current_user().await?.id;
+    let owners = owners(&http).await?;
+    let moderators = admin::Moderators::new(owners.clone(), &config.admin_role_ids);
+
+    // text commands are kept until every member gets used to the slash commands.
+    let text_commands_enabled = config.text_commands_enabled;
 
     let framework = StandardFramework::new()
-        .configure(|c| c.prefix("").on_mention(Some(bot_id)))
+        .configure(|c| c.prefix("").on_mention(Some(bot_id)).owners(owners))
         .before(hook::before)
         .after(hook::after)
         .unrecognised_command(hook::unknown_command)
         .on_dispatch_error(hook::dispatch_error)
         .help(&hook::HELP)
-        .group(&displayname::DISPLAYNAME_GROUP);
+        .group(&admin::ADMIN_GROUP)
+        .group(&displayname::DISPLAYNAME_GROUP)
+        .group(&link::LINK_GROUP)
+        .group(&profile::PROFILE_GROUP)
+        .group(&status::STATUS_GROUP)
+        .group(&unlink::UNLINK_GROUP);
 
     let mut intents = GatewayIntents::default();
-    intents.insert(GatewayIntents::GUILD_MESSAGES);
+    if text_commands_enabled {
+        intents.insert(GatewayIntents::GUILD_MESSAGES);
+    }
+    // privileged, has to be enabled in the developer portal too.
+    if config.member_events_enabled {
+        intents.insert(GatewayIntents::GUILD_MEMBERS);
+    } else {
+        tracing::info!("member events are disabled, membership is caught up with on startup only");
+    }
 
-    let mut client = Client::builder(token, intents)
-        .event_handler(hook::Handler)
-        .framework(framework)
-        .type_map_insert::<FirebaseUseCaseContainer>(usecases)
-        .await
-        .context("could not start discord bot")?;
+    let mut builder = Client::builder(&config.token, intents)
+        .event_handler(hook::Handler::default())
+        .type_map_insert::<DynUseCaseContainer>(usecases)
+        .type_map_insert::<admin::Moderators>(Arc::new(moderators));
+    if text_commands_enabled {
+        builder = builder.framework(framework);
+    } else {
+        tracing::info!("text commands are disabled, only slash commands are available");
+    }
+    let mut client = builder.await.context("could not start discord bot")?;
 
     submit_signal_handler(&client, async {
         #[allow(clippy::expect_used)]
@@ -63,6 +99,27 @@ pub(crate) async fn start_discord_bot(
         .context("could not start discord client")
 }
 
+/// Whether the command failed because the member is not registered, rather than on an internal error.
+fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<RepositoryError>(),
+        Some(RepositoryError::NotFound { .. })
+    )
+}
+
+/// Owners of the bot application, or the members of its team.
+async fn owners(http: &Http) -> anyhow::Result<HashSet<UserId>> {
+    let info = http
+        .get_current_application_info()
+        .await
+        .context("could not get application info from discord")?;
+
+    Ok(match info.team {
+        Some(team) => team.members.iter().map(|member| member.user.id).collect(),
+        None => HashSet::from([info.owner.id]),
+    })
+}
+
 fn submit_signal_handler(client: &Client, waiter: impl Future + Send + 'static) {
     let shard_manager = Arc::clone(&client.shard_manager);
 
@@ -71,3 +128,23 @@ fn submit_signal_handler(client: &Client, waiter: impl Future + Send + 'static)
         shard_manager.lock().await.shutdown_all().await;
     });
 }
+
+/// Reply of a command, shared by the text and the slash commands.
+enum Reply {
+    Content(String),
+    Embed(CreateEmbed),
+}
+
+impl Reply {
+    async fn send_to(self, ctx: &Context, message: &Message) -> serenity::Result<Message> {
+        match self {
+            Reply::Content(content) => message.reply(ctx, content).await,
+            Reply::Embed(embed) => {
+                message
+                    .channel_id
+                    .send_message(ctx, |m| m.reference_message(message).set_embed(embed))
+                    .await
+            }
+        }
+    }
+}
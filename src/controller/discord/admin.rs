@@ -0,0 +1,505 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use chrono::Utc;
+use serenity::client::Context;
+use serenity::framework::standard::macros::{check, command, group};
+use serenity::framework::standard::{Args, CommandOptions, CommandResult, Reason};
+use serenity::model::channel::Message;
+use serenity::model::id::{RoleId, UserId};
+use serenity::model::mention::Mentionable as _;
+use serenity::prelude::TypeMapKey;
+
+use crate::usecase::audit::Actor;
+use crate::usecase::members::DisplayNameError;
+use crate::usecase::oauth2::OAuth2Error;
+use crate::usecase::DynUseCaseContainer;
+
+use super::displayname::display_name_error_message;
+use super::is_not_found;
+
+const CONFIRMATION: &str = "confirm";
+pub(super) const FAILURE_REPLY: &str =
+    "メンバー情報を操作できませんでした. しばらくしてから再度お試しください.";
+const NOT_FOUND_REPLY: &str = "メンバー情報が見つかりませんでした.";
+
+#[group]
+#[prefixes("admin")]
+#[summary = "管理者向けコマンド"]
+#[description = "モデレーターが他のメンバーの情報を操作するコマンド"]
+#[only_in(guilds)]
+#[checks(Moderator)]
+#[commands(
+    set_display_name,
+    unset_display_name,
+    refresh_token,
+    deactivate,
+    reactivate,
+    needs_reauth,
+    delete
+)]
+pub(crate) struct Admin;
+
+/// Members allowed to run the admin commands.
+pub(super) struct Moderators {
+    /// Configured with `ADMIN_ROLE_IDS`.
+    role_ids: Vec<RoleId>,
+    /// Owners of the bot application, who are moderators regardless of their roles.
+    owners: HashSet<UserId>,
+}
+
+impl TypeMapKey for Moderators {
+    type Value = Arc<Self>;
+}
+
+impl Moderators {
+    pub(super) fn new(owners: HashSet<UserId>, role_ids: &[u64]) -> Self {
+        Self {
+            role_ids: role_ids.iter().copied().map(RoleId).collect(),
+            owners,
+        }
+    }
+
+    /// Shared with the slash commands, which carry the roles of the member as well.
+    pub(super) fn contains(&self, user_id: UserId, roles: &[RoleId]) -> bool {
+        self.owners.contains(&user_id)
+            || roles.iter().any(|role_id| self.role_ids.contains(role_id))
+    }
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[check]
+#[name = "Moderator"]
+async fn moderator_check(
+    ctx: &Context,
+    message: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> Result<(), Reason> {
+    let data = ctx.data.read().await;
+    let Some(moderators) = data.get::<Moderators>() else {
+        return Err(Reason::Log("could not get moderators from serenity context".to_string()));
+    };
+
+    let roles = message
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| member.roles.as_slice());
+    if moderators.contains(message.author.id, roles) {
+        Ok(())
+    } else {
+        Err(Reason::UserAndLog {
+            user: "このコマンドはモデレーターのみ使用できます".to_string(),
+            log: format!("user is not a moderator: userId: {}", message.author.id),
+        })
+    }
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("setname")]
+#[description = "指定したメンバーの表示名を変更する"]
+#[usage = "<メンバー> <表示名>"]
+async fn set_display_name(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let Some(target) = parse_target(ctx, message, &mut args, "setname").await? else {
+        return Ok(());
+    };
+    let Ok(new_display_name) = args.single_quoted::<String>() else {
+        log_action(message.author.id, "setname", Some(target), "missing display name");
+        message.reply(ctx, "表示名を入力してください").await?;
+        return Ok(());
+    };
+
+    let result = set_name(usecases, message.author.id, target, new_display_name).await;
+    reply(ctx, message, result).await
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("unsetname")]
+#[description = "指定したメンバーの表示名をデフォルトにリセットする"]
+#[usage = "<メンバー>"]
+async fn unset_display_name(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let Some(target) = parse_target(ctx, message, &mut args, "unsetname").await? else {
+        return Ok(());
+    };
+
+    let result = unset_name(usecases, message.author.id, target).await;
+    reply(ctx, message, result).await
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("refresh")]
+#[description = "指定したメンバーのアクセストークンを期限に関わらず更新する"]
+#[usage = "<メンバー>"]
+async fn refresh_token(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let Some(target) = parse_target(ctx, message, &mut args, "refresh").await? else {
+        return Ok(());
+    };
+
+    let result = refresh(usecases, message.author.id, target).await;
+    reply(ctx, message, result).await
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("deactivate")]
+#[description = "指定したメンバーをギルドから脱退したものとして扱う. `admin reactivate` するまでギルドに参加していても非アクティブのままになる"]
+#[usage = "<メンバー>"]
+async fn deactivate(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let Some(target) = parse_target(ctx, message, &mut args, "deactivate").await? else {
+        return Ok(());
+    };
+
+    let result = set_active(usecases, message.author.id, target, false).await;
+    reply(ctx, message, result).await
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("reactivate")]
+#[description = "`admin deactivate` で非アクティブにしたメンバーをアクティブに戻す"]
+#[usage = "<メンバー>"]
+async fn reactivate(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let Some(target) = parse_target(ctx, message, &mut args, "reactivate").await? else {
+        return Ok(());
+    };
+
+    let result = set_active(usecases, message.author.id, target, true).await;
+    reply(ctx, message, result).await
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("needsreauth")]
+#[description = "OAuth2の認可を再度与える必要があるメンバーの一覧を表示する"]
+async fn needs_reauth(ctx: &Context, message: &Message) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let reply = needs_reauth_list(usecases, message.author.id).await?;
+    // the list should not notify the members in it.
+    message
+        .channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(message)
+                .content(reply)
+                .allowed_mentions(|mentions| mentions.empty_parse())
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("delete")]
+#[description = "指定したメンバーのOAuth2の認可を取り消し, 保存されているメンバー情報を全て削除する"]
+#[usage = "<メンバー> confirm"]
+async fn delete(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let Some(target) = parse_target(ctx, message, &mut args, "delete").await? else {
+        return Ok(());
+    };
+    if args.single::<String>().ok().as_deref() != Some(CONFIRMATION) {
+        log_action(message.author.id, "delete", Some(target), "not confirmed");
+        message
+            .reply(
+                ctx,
+                format!(
+                    "{}のメンバー情報を全て削除します. よろしければ `admin delete {} {CONFIRMATION}` と入力してください.",
+                    target.mention(),
+                    target
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let result = delete_member(usecases, message.author.id, target).await;
+    reply(ctx, message, result).await
+}
+
+/// Shared with the slash command, returns the reply.
+pub(super) async fn set_name(
+    usecases: &DynUseCaseContainer,
+    moderator: UserId,
+    target: UserId,
+    new_display_name: String,
+) -> anyhow::Result<String> {
+    match usecases
+        .members
+        .update_member_display_name(target.to_string(), new_display_name, &actor(moderator))
+        .await
+    {
+        Ok(display_name) => {
+            log_action(moderator, "setname", Some(target), "succeeded");
+            sync_member(usecases, target).await;
+            Ok(format!(
+                "{}の表示名を{display_name}に変更しました",
+                target.mention()
+            ))
+        }
+        Err(err) => {
+            if let Some(cause) = err.downcast_ref::<DisplayNameError>() {
+                log_action(moderator, "setname", Some(target), "invalid display name");
+                Ok(display_name_error_message(cause))
+            } else if is_not_found(&err) {
+                log_action(moderator, "setname", Some(target), "not found");
+                Ok(NOT_FOUND_REPLY.to_string())
+            } else {
+                log_action(moderator, "setname", Some(target), "failed");
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Shared with the slash command, returns the reply.
+pub(super) async fn unset_name(
+    usecases: &DynUseCaseContainer,
+    moderator: UserId,
+    target: UserId,
+) -> anyhow::Result<String> {
+    match usecases
+        .members
+        .unset_member_display_name(target.to_string(), &actor(moderator))
+        .await
+    {
+        Ok(()) => {
+            log_action(moderator, "unsetname", Some(target), "succeeded");
+            sync_member(usecases, target).await;
+            Ok(format!(
+                "{}の表示名をデフォルトにリセットしました",
+                target.mention()
+            ))
+        }
+        Err(err) if is_not_found(&err) => {
+            log_action(moderator, "unsetname", Some(target), "not found");
+            Ok(NOT_FOUND_REPLY.to_string())
+        }
+        Err(err) => {
+            log_action(moderator, "unsetname", Some(target), "failed");
+            Err(err)
+        }
+    }
+}
+
+/// Shared with the slash command, returns the reply.
+pub(super) async fn refresh(
+    usecases: &DynUseCaseContainer,
+    moderator: UserId,
+    target: UserId,
+) -> anyhow::Result<String> {
+    match usecases
+        .oauth2
+        .force_refresh_token(&target.to_string(), &actor(moderator))
+        .await
+    {
+        Ok(true) => {
+            log_action(moderator, "refresh", Some(target), "succeeded");
+            Ok(format!(
+                "{}のアクセストークンを更新しました",
+                target.mention()
+            ))
+        }
+        Ok(false) => {
+            log_action(moderator, "refresh", Some(target), "not found");
+            Ok(NOT_FOUND_REPLY.to_string())
+        }
+        Err(err)
+            if matches!(
+                err.downcast_ref::<OAuth2Error>(),
+                Some(OAuth2Error::AuthorizationRevoked { .. })
+            ) =>
+        {
+            log_action(moderator, "refresh", Some(target), "authorization revoked");
+            Ok(format!(
+                "{}はOAuth2の認可を取り消しているため, 更新できませんでした",
+                target.mention()
+            ))
+        }
+        Err(err) => {
+            log_action(moderator, "refresh", Some(target), "failed");
+            Err(err)
+        }
+    }
+}
+
+/// Shared with the slash commands, returns the reply.
+/// Members who are not in the guild anymore are deactivated again by the next reconciliation.
+pub(super) async fn set_active(
+    usecases: &DynUseCaseContainer,
+    moderator: UserId,
+    target: UserId,
+    active: bool,
+) -> anyhow::Result<String> {
+    let action = if active { "reactivate" } else { "deactivate" };
+    let left_at = if active { None } else { Some(Utc::now()) };
+
+    let updated = usecases
+        .members_service
+        .update_membership(&target.to_string(), left_at, &actor(moderator))
+        .await
+        .inspect_err(|_| log_action(moderator, action, Some(target), "failed"))?;
+
+    if !updated {
+        log_action(moderator, action, Some(target), "not found");
+        return Ok(NOT_FOUND_REPLY.to_string());
+    }
+    log_action(moderator, action, Some(target), "succeeded");
+
+    Ok(if active {
+        format!("{}をアクティブに戻しました", target.mention())
+    } else {
+        format!("{}を非アクティブにしました", target.mention())
+    })
+}
+
+/// Shared with the slash command, returns the reply. It mentions the members, so it has to be
+/// sent without notifying them.
+pub(super) async fn needs_reauth_list(
+    usecases: &DynUseCaseContainer,
+    moderator: UserId,
+) -> anyhow::Result<String> {
+    let lines = usecases
+        .members
+        .get_all_members()
+        .await
+        .inspect_err(|_| log_action(moderator, "needsreauth", None, "failed"))?
+        .into_iter()
+        .filter_map(|member| {
+            member.needs_reauth_since.map(|since| {
+                format!(
+                    "<@{}> ({}に取り消し)",
+                    member.discord_user_id,
+                    since.format("%Y-%m-%d %H:%M:%S UTC")
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    log_action(moderator, "needsreauth", None, "succeeded");
+
+    Ok(if lines.is_empty() {
+        "再認可が必要なメンバーはいません".to_string()
+    } else {
+        format!(
+            "再認可が必要なメンバー ({}人):\n{}",
+            lines.len(),
+            lines.join("\n")
+        )
+    })
+}
+
+/// Shared with the slash command, returns the reply. The caller asks for the confirmation.
+pub(super) async fn delete_member(
+    usecases: &DynUseCaseContainer,
+    moderator: UserId,
+    target: UserId,
+) -> anyhow::Result<String> {
+    let deleted = usecases
+        .oauth2
+        .delete_member(&target.to_string(), &actor(moderator))
+        .await
+        .inspect_err(|_| log_action(moderator, "delete", Some(target), "failed"))?;
+
+    if !deleted {
+        log_action(moderator, "delete", Some(target), "not found");
+        return Ok(NOT_FOUND_REPLY.to_string());
+    }
+    log_action(moderator, "delete", Some(target), "succeeded");
+
+    Ok(format!(
+        "{}のOAuth2の認可を取り消し, メンバー情報を削除しました",
+        target.mention()
+    ))
+}
+
+/// Parses the member given as a mention or an id, replying to the moderator when it is missing.
+async fn parse_target(
+    ctx: &Context,
+    message: &Message,
+    args: &mut Args,
+    action: &str,
+) -> anyhow::Result<Option<UserId>> {
+    match args.single::<UserId>() {
+        Ok(target) => Ok(Some(target)),
+        Err(_) => {
+            log_action(message.author.id, action, None, "missing target");
+            message
+                .reply(ctx, "対象のメンバーをメンションかIDで指定してください")
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+fn actor(moderator: UserId) -> Actor {
+    Actor::Moderator(moderator.to_string())
+}
+
+/// Logs every attempt, including the ones which changed nothing.
+pub(super) fn log_action(moderator: UserId, action: &str, target: Option<UserId>, outcome: &str) {
+    tracing::info!(
+        "moderator ran admin command: moderatorId: {}, action: {}, targetId: {:?}, outcome: {}",
+        moderator,
+        action,
+        target.map(|user_id| user_id.to_string()),
+        outcome
+    );
+}
+
+/// Tells the moderator when the command failed, leaving the cause to the log.
+async fn reply(ctx: &Context, message: &Message, result: anyhow::Result<String>) -> CommandResult {
+    match result {
+        Ok(reply) => {
+            message.reply(ctx, reply).await?;
+            Ok(())
+        }
+        Err(err) => {
+            message.reply(ctx, FAILURE_REPLY).await?;
+            Err(err.into())
+        }
+    }
+}
+
+/// Reflects the change to the API without waiting for the next synchronization.
+async fn sync_member(usecases: &DynUseCaseContainer, target: UserId) {
+    if let Err(err) = usecases
+        .members_service
+        .sync_member(&target.to_string())
+        .await
+    {
+        tracing::warn!(
+            "could not sync member, it is synced on next tick: userId: {}, error: {}",
+            target,
+            err
+        );
+    }
+}
@@ -4,7 +4,14 @@ use serenity::framework::standard::macros::{command, group};
 use serenity::framework::standard::{Args, CommandResult};
 use serenity::model::channel::Message;
 
-use crate::usecase::firebase::FirebaseUseCaseContainer;
+use crate::usecase::audit::Actor;
+use crate::usecase::members::DisplayNameError;
+use crate::usecase::DynUseCaseContainer;
+
+use super::is_not_found;
+
+const NOT_FOUND_REPLY: &str =
+    "メンバー情報が見つかりませんでした. 先にOAuth2にて認可を与えてください.";
 
 #[group]
 #[prefixes("displayname")]
@@ -19,7 +26,7 @@ pub(crate) struct DisplayName;
 async fn set_display_name(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
     let data = ctx.data.read().await;
     let usecases = data
-        .get::<FirebaseUseCaseContainer>()
+        .get::<DynUseCaseContainer>()
         .context("could not get usecase container from serenity context")?;
 
     let Ok(new_display_name) = args
@@ -37,35 +44,8 @@ async fn set_display_name(ctx: &Context, message: &Message, mut args: Args) -> C
             return Ok(());
         };
 
-    if usecases
-        .members
-        .update_member_display_name(message.author.id.to_string(), new_display_name.clone())
-        .await
-        .is_ok()
-    {
-        message
-            .reply(
-                ctx,
-                format!("API上の表示名を{new_display_name}に変更しました"),
-            )
-            .await?;
-        tracing::info!(
-            "updated user display name: userId: {id}, displayName: {displayName}",
-            id = message.author.id.to_string(),
-            displayName = new_display_name
-        );
-    } else {
-        message
-            .reply(
-                ctx,
-                "メンバー情報が見つかりませんでした. 先にOAuth2にて認可を与えてください.",
-            )
-            .await?;
-        tracing::info!(
-            "could not get member data: userId: {id}",
-            id = message.author.id.to_string(),
-        );
-    }
+    let reply = set(usecases, message.author.id.to_string(), new_display_name).await;
+    message.reply(ctx, reply).await?;
 
     Ok(())
 }
@@ -76,21 +56,119 @@ async fn set_display_name(ctx: &Context, message: &Message, mut args: Args) -> C
 async fn unset_display_name(ctx: &Context, message: &Message) -> CommandResult {
     let data = ctx.data.read().await;
     let usecases = data
-        .get::<FirebaseUseCaseContainer>()
+        .get::<DynUseCaseContainer>()
         .context("could not get usecase container from serenity context")?;
 
-    usecases
+    let reply = unset(usecases, message.author.id.to_string()).await?;
+    message.reply(ctx, reply).await?;
+
+    Ok(())
+}
+
+/// Shared with the slash command, returns the reply.
+pub(super) async fn set(
+    usecases: &DynUseCaseContainer,
+    discord_user_id: String,
+    new_display_name: String,
+) -> String {
+    match usecases
         .members
-        .unset_member_display_name(message.author.id.to_string())
-        .await?;
+        .update_member_display_name(
+            discord_user_id.clone(),
+            new_display_name,
+            &Actor::Member(discord_user_id.clone()),
+        )
+        .await
+    {
+        Ok(display_name) => {
+            tracing::info!(
+                "updated user display name: userId: {id}, displayName: {displayName}",
+                id = discord_user_id,
+                displayName = display_name
+            );
+            sync_member(usecases, &discord_user_id).await;
+
+            format!("API上の表示名を{display_name}に変更しました")
+        }
+        Err(err) => {
+            if let Some(cause) = err.downcast_ref::<DisplayNameError>() {
+                return display_name_error_message(cause);
+            }
+            if !is_not_found(&err) {
+                tracing::error!(
+                    "could not update display name: userId: {id}, error: {err:#}",
+                    id = discord_user_id
+                );
+                return "表示名を変更できませんでした. しばらくしてから再度お試しください."
+                    .to_string();
+            }
+            tracing::info!(
+                "could not get member data: userId: {id}",
+                id = discord_user_id
+            );
+
+            NOT_FOUND_REPLY.to_string()
+        }
+    }
+}
+
+pub(super) fn display_name_error_message(err: &DisplayNameError) -> String {
+    match err {
+        DisplayNameError::Empty => {
+            "表示名が空です. 1文字以上の表示名を入力してください.".to_string()
+        }
+        DisplayNameError::TooLong { max_length } => {
+            format!("表示名が長すぎます. {max_length}文字以内で入力してください.")
+        }
+        // the character may not be visible, so its code point is shown instead.
+        DisplayNameError::ForbiddenCharacter { character } => format!(
+            "表示名に使用できない文字(U+{:04X})が含まれています.",
+            u32::from(*character)
+        ),
+        DisplayNameError::Mention => "表示名にメンションを含めることはできません.".to_string(),
+        DisplayNameError::BannedWord => "表示名に使用できない語句が含まれています.".to_string(),
+    }
+}
 
-    message
-        .reply(ctx, "API上の表示名をデフォルトにリセットしました")
-        .await?;
+/// Shared with the slash command, returns the reply.
+pub(super) async fn unset(
+    usecases: &DynUseCaseContainer,
+    discord_user_id: String,
+) -> anyhow::Result<String> {
+    if let Err(err) = usecases
+        .members
+        .unset_member_display_name(
+            discord_user_id.clone(),
+            &Actor::Member(discord_user_id.clone()),
+        )
+        .await
+    {
+        if !is_not_found(&err) {
+            return Err(err);
+        }
+        tracing::info!(
+            "could not get member data: userId: {id}",
+            id = discord_user_id
+        );
+        return Ok(NOT_FOUND_REPLY.to_string());
+    }
 
     tracing::info!(
         "unset user display name: userId: {id}",
-        id = message.author.id.to_string(),
+        id = discord_user_id
     );
-    Ok(())
+    sync_member(usecases, &discord_user_id).await;
+
+    Ok("API上の表示名をデフォルトにリセットしました".to_string())
+}
+
+/// Reflects the new display name to the API without waiting for the next synchronization.
+async fn sync_member(usecases: &DynUseCaseContainer, discord_user_id: &str) {
+    if let Err(err) = usecases.members_service.sync_member(discord_user_id).await {
+        tracing::warn!(
+            "could not sync member, it is synced on next tick: userId: {}, error: {}",
+            discord_user_id,
+            err
+        );
+    }
 }
@@ -1,21 +1,121 @@
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serenity::client::{Context, EventHandler};
 use serenity::framework::standard::macros::{help, hook};
 use serenity::framework::standard::{
-    help_commands, Args, CommandGroup, CommandResult, DispatchError, HelpOptions,
+    help_commands, Args, CommandGroup, CommandResult, DispatchError, HelpOptions, Reason,
 };
+use serenity::model::application::interaction::Interaction;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
-use serenity::model::id::UserId;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::user::User;
 
-pub(super) struct Handler;
+use crate::usecase::audit::Actor;
+use crate::usecase::DynUseCaseContainer;
+
+use super::slash;
+
+#[derive(Default)]
+pub(super) struct Handler {
+    /// `ready` is sent again on every reconnection, while the setup has to run once.
+    started: AtomicBool,
+}
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         tracing::info!("Loggined as the bot user '{}'!", ready.user.name);
+
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // membership events are not delivered while the bot is offline.
+        let Some(usecases) = usecases(&ctx).await else {
+            return;
+        };
+        let guild_id = GuildId(usecases.members_service.guild_id());
+        if let Err(err) = slash::register(&ctx, guild_id).await {
+            tracing::error!("{:#}", err);
+        }
+        if let Err(err) = usecases.members_service.reconcile_guild_membership().await {
+            tracing::error!("could not reconcile guild membership: {:#}", err);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Interaction::ApplicationCommand(command) = interaction else {
+            return;
+        };
+        let Some(usecases) = usecases(&ctx).await else {
+            return;
+        };
+
+        if let Err(err) = slash::handle(&ctx, &usecases, &command).await {
+            tracing::error!("slash command returned error: {:#}", err);
+        }
+    }
+
+    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        update_membership(&ctx, new_member.guild_id, &new_member.user, None).await;
+    }
+
+    async fn guild_member_removal(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        user: User,
+        _member_data_if_available: Option<Member>,
+    ) {
+        update_membership(&ctx, guild_id, &user, Some(Utc::now())).await;
+    }
+}
+
+async fn usecases(ctx: &Context) -> Option<Arc<DynUseCaseContainer>> {
+    let data = ctx.data.read().await;
+    let usecases = data.get::<DynUseCaseContainer>().cloned();
+    if usecases.is_none() {
+        tracing::error!("could not get usecase container from serenity context");
+    }
+
+    usecases
+}
+
+async fn update_membership(
+    ctx: &Context,
+    guild_id: GuildId,
+    user: &User,
+    inactive_since: Option<DateTime<Utc>>,
+) {
+    let Some(usecases) = usecases(ctx).await else {
+        return;
+    };
+    if *guild_id.as_u64() != usecases.members_service.guild_id() {
+        return;
+    }
+
+    match usecases
+        .members_service
+        .update_membership(&user.id.to_string(), inactive_since, &Actor::System)
+        .await
+    {
+        Ok(true) => tracing::info!(
+            "updated membership of member: userId: {}, inactiveSince: {:?}",
+            user.id,
+            inactive_since
+        ),
+        Ok(false) => {}
+        Err(err) => tracing::error!(
+            "could not update membership: userId: {}, error: {:#}",
+            user.id,
+            err
+        ),
     }
 }
 
@@ -74,6 +174,15 @@ pub(super) async fn dispatch_error(
     error: DispatchError,
     command_name: &str,
 ) {
+    if let DispatchError::CheckFailed(
+        _,
+        Reason::User(reason) | Reason::UserAndLog { user: reason, .. },
+    ) = &error
+    {
+        if let Err(err) = msg.reply(ctx, reason).await {
+            tracing::error!("could not reply check failure: {}", err);
+        }
+    }
     if let DispatchError::Ratelimited(info) = error {
         // We notify them only once.
         if info.is_first_try {
@@ -0,0 +1,64 @@
+use anyhow::Context as _;
+use serenity::client::Context;
+use serenity::framework::standard::macros::{command, group};
+use serenity::framework::standard::CommandResult;
+use serenity::model::channel::Message;
+use serenity::model::user::User;
+
+use crate::usecase::DynUseCaseContainer;
+
+#[group]
+#[summary = "登録関連コマンド"]
+#[description = "members-dbに登録するためのリンクを発行するコマンド"]
+#[commands(link)]
+pub(crate) struct Link;
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("link")]
+#[description = "OAuth2にて認可を与えるためのリンクをDMで送信する"]
+async fn link(ctx: &Context, message: &Message) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let reply = send_auth_url(ctx, usecases, &message.author).await?;
+    message.reply(ctx, reply).await?;
+
+    Ok(())
+}
+
+/// Shared with the slash command, returns the reply.
+/// The auth-url can only be completed by the user it was sent to.
+pub(super) async fn send_auth_url(
+    ctx: &Context,
+    usecases: &DynUseCaseContainer,
+    user: &User,
+) -> anyhow::Result<String> {
+    let auth_url = usecases
+        .oauth2
+        .authenticate(Some(user.id.to_string()))
+        .await?;
+
+    let sent = user
+        .direct_message(ctx, |message| {
+            message.content(format!(
+                "以下のリンクからOAuth2にて認可を与えてください. このリンクはあなた専用です.\n{auth_url}"
+            ))
+        })
+        .await;
+
+    match sent {
+        Ok(_) => {
+            tracing::info!("sent auth-url: userId: {id}", id = user.id.to_string());
+            Ok("DMに認可用のリンクを送信しました".to_string())
+        }
+        Err(err) => {
+            tracing::info!(
+                "could not send auth-url: userId: {id}, error: {err}",
+                id = user.id.to_string(),
+            );
+            Ok("DMを送信できませんでした. サーバーメンバーからのDMを許可してください.".to_string())
+        }
+    }
+}
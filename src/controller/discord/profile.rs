@@ -0,0 +1,123 @@
+use anyhow::Context as _;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::framework::standard::macros::{command, group};
+use serenity::framework::standard::CommandResult;
+use serenity::model::channel::Message;
+use serenity::model::user::User;
+use serenity::utils::Colour;
+
+use crate::model::MemberListRow;
+use crate::usecase::DynUseCaseContainer;
+
+use super::Reply;
+
+#[group]
+#[summary = "プロフィール関連コマンド"]
+#[description = "members-db APIで公開されている情報を確認するコマンド"]
+#[commands(show_profile)]
+pub(crate) struct Profile;
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("profile")]
+#[description = "members-db APIで公開されている自分の情報を表示する"]
+async fn show_profile(ctx: &Context, message: &Message) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    profile(usecases, &message.author)
+        .await?
+        .send_to(ctx, message)
+        .await?;
+
+    Ok(())
+}
+
+/// Shared with the slash command. Unregistered members get the link to start the authorization.
+pub(super) async fn profile(usecases: &DynUseCaseContainer, user: &User) -> anyhow::Result<Reply> {
+    let discord_user_id = user.id.to_string();
+    // members who were never synchronized are synchronized on their own request.
+    let snapshot = match usecases
+        .members_service
+        .get_member(&discord_user_id)
+        .await?
+    {
+        Some(snapshot) => Some(snapshot),
+        None => {
+            usecases
+                .members_service
+                .sync_member(&discord_user_id)
+                .await?
+        }
+    };
+    let Some(member) = snapshot else {
+        tracing::info!("could not get member data: userId: {id}", id = discord_user_id);
+
+        let reply = match usecases.oauth2.authorization_entry_url() {
+            Some(url) => format!(
+                "メンバー情報が見つかりませんでした. {url} からOAuth2にて認可を与えてください."
+            ),
+            None => "メンバー情報が見つかりませんでした. 先にOAuth2にて認可を与えてください."
+                .to_string(),
+        };
+        return Ok(Reply::Content(reply));
+    };
+
+    Ok(Reply::Embed(embed(user, &member)))
+}
+
+fn embed(user: &User, member: &MemberListRow) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+
+    embed
+        .author(|author| author.name(&user.name).icon_url(user.face()))
+        .title("members-db APIで公開されている情報")
+        .field(
+            "表示名",
+            member.display_name.as_deref().unwrap_or("未設定"),
+            false,
+        )
+        .field("Twitter", connections(&member.twitter), true)
+        .field("GitHub", connections(&member.github), true)
+        .field(
+            "ロール",
+            member
+                .role
+                .as_ref()
+                .map_or("なし", |role| role.name.as_str()),
+            false,
+        )
+        .field(
+            "登録日",
+            member.registered_at.map_or_else(
+                || "不明".to_string(),
+                |at| format!("<t:{}:D>", at.timestamp()),
+            ),
+            false,
+        )
+        .footer(|footer| footer.text("最終同期"))
+        .timestamp(member.synced_at);
+
+    if let Some(colour) = member
+        .role
+        .as_ref()
+        .and_then(|role| u32::from_str_radix(&role.color, 16).ok())
+    {
+        embed.colour(Colour::new(colour));
+    }
+    if member.stale {
+        embed.description("最新の情報を取得できなかったため, 古い情報が表示されています.");
+    }
+
+    embed
+}
+
+fn connections(ids: &[String]) -> String {
+    if ids.is_empty() {
+        "なし".to_string()
+    } else {
+        ids.join("\n")
+    }
+}
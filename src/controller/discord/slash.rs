@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Context as _};
+use serenity::builder::CreateApplicationCommandOption;
+use serenity::client::Context;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::id::{GuildId, UserId};
+
+use crate::usecase::DynUseCaseContainer;
+
+use super::{admin, displayname, link, profile, status, unlink, Reply};
+
+const NOT_CONFIRMED_REPLY: &str =
+    "メンバー情報は削除されませんでした. 削除するにはconfirmにTrueを指定してください.";
+
+/// Registers the slash commands to the guild only, where they become available immediately
+/// unlike global commands.
+#[tracing::instrument(skip(ctx))]
+pub(super) async fn register(ctx: &Context, guild_id: GuildId) -> anyhow::Result<()> {
+    guild_id
+        .set_application_commands(&ctx.http, |commands| {
+            commands.create_application_command(|command| {
+                command
+                    .name("displayname")
+                    .description("members-db APIでの表示名を操作する")
+                    .create_option(|option| {
+                        option
+                            .name("set")
+                            .description("表示名を指定した文字列に変更する")
+                            .kind(CommandOptionType::SubCommand)
+                            .create_sub_option(|sub_option| {
+                                sub_option
+                                    .name("name")
+                                    .description("新しい表示名")
+                                    .kind(CommandOptionType::String)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|option| {
+                        option
+                            .name("unset")
+                            .description("表示名をデフォルトにリセットする")
+                            .kind(CommandOptionType::SubCommand)
+                    })
+            });
+            commands.create_application_command(|command| {
+                command
+                    .name("link")
+                    .description("OAuth2にて認可を与えるためのリンクをDMで送信する")
+            });
+            commands.create_application_command(|command| {
+                command
+                    .name("profile")
+                    .description("members-db APIで公開されている自分の情報を表示する")
+            });
+            commands.create_application_command(|command| {
+                command
+                    .name("status")
+                    .description("OAuth2の認可が有効かどうかを表示する")
+            });
+            commands.create_application_command(|command| {
+                command
+                    .name("unlink")
+                    .description("OAuth2の認可を取り消し, 保存されているメンバー情報を全て削除する")
+                    .create_option(|option| confirm_option(option))
+            });
+            commands.create_application_command(|command| {
+                command
+                    .name("admin")
+                    .description("モデレーターが他のメンバーの情報を操作する")
+                    .dm_permission(false)
+                    .create_option(|option| {
+                        target_subcommand(option, "setname", "指定したメンバーの表示名を変更する")
+                            .create_sub_option(|sub_option| {
+                                sub_option
+                                    .name("name")
+                                    .description("新しい表示名")
+                                    .kind(CommandOptionType::String)
+                                    .required(true)
+                            })
+                    })
+                    .create_option(|option| {
+                        target_subcommand(
+                            option,
+                            "unsetname",
+                            "指定したメンバーの表示名をデフォルトにリセットする",
+                        )
+                    })
+                    .create_option(|option| {
+                        target_subcommand(
+                            option,
+                            "refresh",
+                            "指定したメンバーのアクセストークンを期限に関わらず更新する",
+                        )
+                    })
+                    .create_option(|option| {
+                        target_subcommand(
+                            option,
+                            "deactivate",
+                            "指定したメンバーをギルドから脱退したものとして扱う",
+                        )
+                    })
+                    .create_option(|option| {
+                        target_subcommand(
+                            option,
+                            "reactivate",
+                            "非アクティブにしたメンバーをアクティブに戻す",
+                        )
+                    })
+                    .create_option(|option| {
+                        option
+                            .name("needsreauth")
+                            .description(
+                                "OAuth2の認可を再度与える必要があるメンバーの一覧を表示する",
+                            )
+                            .kind(CommandOptionType::SubCommand)
+                    })
+                    .create_option(|option| {
+                        target_subcommand(
+                            option,
+                            "delete",
+                            "指定したメンバーのOAuth2の認可を取り消し, メンバー情報を全て削除する",
+                        )
+                        .create_sub_option(|sub_option| confirm_option(sub_option))
+                    })
+            })
+        })
+        .await
+        .context("could not register slash commands")?;
+    tracing::info!("registered slash commands to the guild");
+
+    Ok(())
+}
+
+/// Subcommand of `admin` which takes the member to operate on.
+fn target_subcommand<'a>(
+    option: &'a mut CreateApplicationCommandOption,
+    name: &str,
+    description: &str,
+) -> &'a mut CreateApplicationCommandOption {
+    option
+        .name(name)
+        .description(description)
+        .kind(CommandOptionType::SubCommand)
+        .create_sub_option(|sub_option| {
+            sub_option
+                .name("member")
+                .description("対象のメンバー")
+                .kind(CommandOptionType::User)
+                .required(true)
+        })
+}
+
+/// Stands in for typing `confirm` after the text commands which delete the member data.
+fn confirm_option(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .name("confirm")
+        .description("メンバー情報を全て削除してよければTrue")
+        .kind(CommandOptionType::Boolean)
+        .required(true)
+}
+
+/// Replies are ephemeral, so that only the member who ran the command can see them.
+///
+/// The reply is deferred first, since discord drops interactions which are not answered
+/// within three seconds and the commands wait on the database and discord before replying.
+#[tracing::instrument(skip_all, fields(command = %command.data.name))]
+pub(super) async fn handle(
+    ctx: &Context,
+    usecases: &DynUseCaseContainer,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|message| message.ephemeral(true))
+        })
+        .await
+        .context("could not defer reply to slash command")?;
+
+    // the deferred reply keeps showing that the bot is thinking until it is edited.
+    let (reply, result) = match run(ctx, usecases, command).await {
+        Ok(reply) => (reply, Ok(())),
+        Err(err) => (
+            Reply::Content("コマンドを処理できませんでした".to_owned()),
+            Err(err),
+        ),
+    };
+
+    command
+        .edit_original_interaction_response(&ctx.http, |response| {
+            // replies such as the one of `admin needsreauth` should not notify the members in them.
+            response.allowed_mentions(|mentions| mentions.empty_parse());
+            match reply {
+                Reply::Content(content) => response.content(content),
+                Reply::Embed(embed) => response.set_embed(embed),
+            }
+        })
+        .await
+        .context("could not reply to slash command")?;
+
+    result?;
+    tracing::info!("processed slash command");
+
+    Ok(())
+}
+
+async fn run(
+    ctx: &Context,
+    usecases: &DynUseCaseContainer,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<Reply> {
+    match command.data.name.as_str() {
+        "displayname" => Ok(Reply::Content(displayname(usecases, command).await?)),
+        "link" => Ok(Reply::Content(
+            link::send_auth_url(ctx, usecases, &command.user).await?,
+        )),
+        "profile" => profile::profile(usecases, &command.user).await,
+        "status" => Ok(Reply::Content(
+            status::status(usecases, &command.user).await?,
+        )),
+        "unlink" => {
+            let reply = if bool_option(&command.data.options, "confirm")? {
+                unlink::delete_member(usecases, &command.user).await?
+            } else {
+                NOT_CONFIRMED_REPLY.to_owned()
+            };
+            Ok(Reply::Content(reply))
+        }
+        "admin" => Ok(Reply::Content(admin(ctx, usecases, command).await?)),
+        name => Err(anyhow!("unknown slash command '{name}'")),
+    }
+}
+
+async fn displayname(
+    usecases: &DynUseCaseContainer,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<String> {
+    let discord_user_id = command.user.id.to_string();
+    let subcommand = command
+        .data
+        .options
+        .first()
+        .context("subcommand of displayname is not given")?;
+
+    match subcommand.name.as_str() {
+        "set" => {
+            let new_display_name = string_option(&subcommand.options, "name")?;
+
+            Ok(displayname::set(usecases, discord_user_id, new_display_name).await)
+        }
+        "unset" => displayname::unset(usecases, discord_user_id).await,
+        name => Err(anyhow!("unknown subcommand of displayname '{name}'")),
+    }
+}
+
+/// Checked here rather than with the permissions of the command, since the moderators are
+/// configured with `ADMIN_ROLE_IDS` instead of the permissions in the guild.
+async fn admin(
+    ctx: &Context,
+    usecases: &DynUseCaseContainer,
+    command: &ApplicationCommandInteraction,
+) -> anyhow::Result<String> {
+    let data = ctx.data.read().await;
+    let moderators = data
+        .get::<admin::Moderators>()
+        .context("could not get moderators from serenity context")?;
+    let moderator = command.user.id;
+    let roles = command
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| member.roles.as_slice());
+    if !moderators.contains(moderator, roles) {
+        tracing::info!("user is not a moderator: userId: {}", moderator);
+        return Ok("このコマンドはモデレーターのみ使用できます".to_owned());
+    }
+
+    let subcommand = command
+        .data
+        .options
+        .first()
+        .context("subcommand of admin is not given")?;
+    let options = &subcommand.options;
+
+    let result = match subcommand.name.as_str() {
+        "setname" => {
+            let target = user_option(options, "member")?;
+            let new_display_name = string_option(options, "name")?;
+            admin::set_name(usecases, moderator, target, new_display_name).await
+        }
+        "unsetname" => {
+            admin::unset_name(usecases, moderator, user_option(options, "member")?).await
+        }
+        "refresh" => admin::refresh(usecases, moderator, user_option(options, "member")?).await,
+        "deactivate" => {
+            admin::set_active(usecases, moderator, user_option(options, "member")?, false).await
+        }
+        "reactivate" => {
+            admin::set_active(usecases, moderator, user_option(options, "member")?, true).await
+        }
+        "needsreauth" => admin::needs_reauth_list(usecases, moderator).await,
+        "delete" => {
+            let target = user_option(options, "member")?;
+            if bool_option(options, "confirm")? {
+                admin::delete_member(usecases, moderator, target).await
+            } else {
+                admin::log_action(moderator, "delete", Some(target), "not confirmed");
+                Ok(NOT_CONFIRMED_REPLY.to_owned())
+            }
+        }
+        name => return Err(anyhow!("unknown subcommand of admin '{name}'")),
+    };
+
+    // the same reply as the text commands, rather than the generic one of the slash commands.
+    match result {
+        Ok(reply) => Ok(reply),
+        Err(err) => {
+            tracing::error!("admin command returned error: {:#}", err);
+            Ok(admin::FAILURE_REPLY.to_owned())
+        }
+    }
+}
+
+fn option_value<'a>(
+    options: &'a [CommandDataOption],
+    name: &str,
+) -> anyhow::Result<&'a CommandDataOptionValue> {
+    options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.resolved.as_ref())
+        .with_context(|| format!("{name} option is not given"))
+}
+
+fn string_option(options: &[CommandDataOption], name: &str) -> anyhow::Result<String> {
+    match option_value(options, name)? {
+        CommandDataOptionValue::String(value) => Ok(value.to_owned()),
+        _ => Err(anyhow!("{name} option is not a string")),
+    }
+}
+
+fn bool_option(options: &[CommandDataOption], name: &str) -> anyhow::Result<bool> {
+    match option_value(options, name)? {
+        CommandDataOptionValue::Boolean(value) => Ok(*value),
+        _ => Err(anyhow!("{name} option is not a boolean")),
+    }
+}
+
+fn user_option(options: &[CommandDataOption], name: &str) -> anyhow::Result<UserId> {
+    match option_value(options, name)? {
+        CommandDataOptionValue::User(user, _) => Ok(user.id),
+        _ => Err(anyhow!("{name} option is not a user")),
+    }
+}
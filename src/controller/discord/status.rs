@@ -0,0 +1,53 @@
+use anyhow::Context as _;
+use serenity::client::Context;
+use serenity::framework::standard::macros::{command, group};
+use serenity::framework::standard::CommandResult;
+use serenity::model::channel::Message;
+use serenity::model::user::User;
+
+use crate::usecase::DynUseCaseContainer;
+
+#[group]
+#[summary = "登録状態関連コマンド"]
+#[description = "members-dbへの登録状態を確認するコマンド"]
+#[commands(show_status)]
+pub(crate) struct Status;
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("status")]
+#[description = "OAuth2の認可が有効かどうかを表示する"]
+async fn show_status(ctx: &Context, message: &Message) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    let reply = status(usecases, &message.author).await?;
+    message.reply(ctx, reply).await?;
+
+    Ok(())
+}
+
+/// Shared with the slash command, returns the reply.
+pub(super) async fn status(usecases: &DynUseCaseContainer, user: &User) -> anyhow::Result<String> {
+    let member = usecases.members.get_member(&user.id.to_string()).await?;
+
+    let reply = match member {
+        None => {
+            "メンバー情報が見つかりませんでした. 先にOAuth2にて認可を与えてください.".to_string()
+        }
+        Some(member) => match member.needs_reauth_since {
+            Some(since) => format!(
+                "{}にOAuth2の認可が取り消されました. 再度OAuth2にて認可を与えてください.",
+                since.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+            None => "OAuth2の認可は有効です.".to_string(),
+        },
+    };
+
+    tracing::info!(
+        "replied member status: userId: {id}",
+        id = user.id.to_string(),
+    );
+    Ok(reply)
+}
@@ -0,0 +1,66 @@
+use anyhow::Context as _;
+use serenity::client::Context;
+use serenity::framework::standard::macros::{command, group};
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::model::channel::Message;
+use serenity::model::user::User;
+
+use crate::usecase::audit::Actor;
+use crate::usecase::DynUseCaseContainer;
+
+const CONFIRMATION: &str = "confirm";
+
+#[group]
+#[summary = "登録解除関連コマンド"]
+#[description = "members-dbからメンバー情報を削除するコマンド"]
+#[commands(unlink)]
+pub(crate) struct Unlink;
+
+#[allow(clippy::extra_unused_type_parameters)]
+#[command("unlink")]
+#[description = "OAuth2の認可を取り消し, 保存されているメンバー情報を全て削除する"]
+#[usage = "confirm"]
+async fn unlink(ctx: &Context, message: &Message, mut args: Args) -> CommandResult {
+    let data = ctx.data.read().await;
+    let usecases = data
+        .get::<DynUseCaseContainer>()
+        .context("could not get usecase container from serenity context")?;
+
+    if args.single::<String>().ok().as_deref() != Some(CONFIRMATION) {
+        message
+            .reply(
+                ctx,
+                format!("メンバー情報を全て削除します. よろしければ `unlink {CONFIRMATION}` と入力してください."),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let reply = delete_member(usecases, &message.author).await?;
+    message.reply(ctx, reply).await?;
+
+    Ok(())
+}
+
+/// Shared with the slash command, returns the reply. The caller asks for the confirmation.
+pub(super) async fn delete_member(
+    usecases: &DynUseCaseContainer,
+    user: &User,
+) -> anyhow::Result<String> {
+    let discord_user_id = user.id.to_string();
+    let deleted = usecases
+        .oauth2
+        .delete_member(&discord_user_id, &Actor::Member(discord_user_id.clone()))
+        .await?;
+
+    if deleted {
+        tracing::info!("deleted member data: userId: {id}", id = discord_user_id);
+        Ok("OAuth2の認可を取り消し, メンバー情報を削除しました".to_string())
+    } else {
+        tracing::info!(
+            "could not get member data: userId: {id}",
+            id = discord_user_id
+        );
+        Ok("メンバー情報が見つかりませんでした.".to_string())
+    }
+}
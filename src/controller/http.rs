@@ -1,3 +1,4 @@
+pub(crate) mod admin;
 pub(crate) mod api;
 pub(crate) mod oauth2;
 
@@ -11,27 +12,39 @@ use axum::response::IntoResponse;
 use axum::Router;
 use tokio::signal;
 
-use crate::infra::repository::firestore::{MemberDataRepositoryImpl, OAuth2RepositoryImpl};
+use crate::config::HttpConfig;
+use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
 use crate::service::members::MembersService;
-use crate::usecase::firebase::FirebaseUseCaseContainer;
+use crate::usecase::audit::AuditLogUseCase;
 use crate::usecase::members::MembersUseCase;
 use crate::usecase::oauth2::OAuth2UseCase;
-use crate::util::safe_env;
+use crate::usecase::UseCaseContainer;
 
-#[tracing::instrument(skip(usecases))]
-pub(crate) async fn start_http_server(
-    usecases: Arc<FirebaseUseCaseContainer>,
-) -> anyhow::Result<()> {
-    let state = AppState { usecases };
+#[tracing::instrument(skip(usecases, config))]
+pub(crate) async fn start_http_server<MR, OR>(
+    usecases: Arc<UseCaseContainer<MR, OR>>,
+    config: &HttpConfig,
+) -> anyhow::Result<()>
+where
+    MR: MemberDataRepository + Clone + Send + Sync + 'static,
+    OR: OAuth2Repository + Clone + Send + Sync + 'static,
+{
+    let admin_api_token = config.admin_api_token.clone();
+    if admin_api_token.is_none() {
+        tracing::info!("ADMIN_API_TOKEN is not set, the admin api is disabled");
+    }
+    let state = AppState {
+        usecases,
+        admin_api_token: AdminApiToken(admin_api_token),
+    };
 
     let app = Router::new()
-        .nest("/oauth2", oauth2::route())
-        .nest("/api/v1", api::route())
+        .nest("/oauth2", oauth2::route::<MR, OR>())
+        .nest("/api/v1", api::route::<MR, OR>())
+        .nest("/api/v1/admin", admin::route::<MR, OR>())
         .with_state(state);
 
-    let port = safe_env("PORT")?.parse::<u16>()?;
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let addr = SocketAddr::new(config.bind_address, config.port);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
         .with_graceful_shutdown(shutdown_signal())
@@ -70,30 +83,47 @@ async fn shutdown_signal() {
 }
 
 #[derive(Clone)]
-pub(crate) struct AppState {
-    usecases: Arc<FirebaseUseCaseContainer>,
+pub(crate) struct AppState<MR: Clone, OR: Clone> {
+    usecases: Arc<UseCaseContainer<MR, OR>>,
+    admin_api_token: AdminApiToken,
 }
 
-impl FromRef<AppState> for Arc<FirebaseUseCaseContainer> {
-    fn from_ref(input: &AppState) -> Self {
+/// Bearer token of the admin api, which is disabled when it is `None`.
+#[derive(Clone)]
+pub(crate) struct AdminApiToken(Option<String>);
+
+impl<MR: Clone, OR: Clone> FromRef<AppState<MR, OR>> for AdminApiToken {
+    fn from_ref(input: &AppState<MR, OR>) -> Self {
+        input.admin_api_token.clone()
+    }
+}
+
+impl<MR: Clone, OR: Clone> FromRef<AppState<MR, OR>> for Arc<UseCaseContainer<MR, OR>> {
+    fn from_ref(input: &AppState<MR, OR>) -> Self {
         Arc::clone(&input.usecases)
     }
 }
 
-impl FromRef<AppState> for OAuth2UseCase<MemberDataRepositoryImpl, OAuth2RepositoryImpl> {
-    fn from_ref(input: &AppState) -> Self {
+impl<MR: Clone, OR: Clone> FromRef<AppState<MR, OR>> for OAuth2UseCase<MR, OR> {
+    fn from_ref(input: &AppState<MR, OR>) -> Self {
         input.usecases.oauth2.clone()
     }
 }
 
-impl FromRef<AppState> for MembersUseCase<MemberDataRepositoryImpl> {
-    fn from_ref(input: &AppState) -> Self {
+impl<MR: Clone, OR: Clone> FromRef<AppState<MR, OR>> for MembersUseCase<MR> {
+    fn from_ref(input: &AppState<MR, OR>) -> Self {
         input.usecases.members.clone()
     }
 }
 
-impl FromRef<AppState> for MembersService<MemberDataRepositoryImpl, OAuth2RepositoryImpl> {
-    fn from_ref(input: &AppState) -> Self {
+impl<MR: Clone, OR: Clone> FromRef<AppState<MR, OR>> for AuditLogUseCase<MR> {
+    fn from_ref(input: &AppState<MR, OR>) -> Self {
+        input.usecases.audit_log.clone()
+    }
+}
+
+impl<MR: Clone, OR: Clone> FromRef<AppState<MR, OR>> for MembersService<MR, OR> {
+    fn from_ref(input: &AppState<MR, OR>) -> Self {
         input.usecases.members_service.clone()
     }
 }
@@ -0,0 +1,118 @@
+use anyhow::anyhow;
+use axum::extract::{Path, Query, State};
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router, TypedHeader};
+use serde::Deserialize;
+
+use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
+use crate::model::{AuditLogEntry, MemberListRow};
+use crate::service::members::MembersService;
+use crate::usecase::audit::AuditLogUseCase;
+
+use super::{AdminApiToken, AppState, HttpError};
+
+const DEFAULT_AUDIT_LOG_LIMIT: u32 = 100;
+const MAX_AUDIT_LOG_LIMIT: u32 = 1000;
+
+/// Every route needs `ADMIN_API_TOKEN` as the bearer token, and is disabled unless it is set.
+pub(crate) fn route<MR, OR>() -> Router<AppState<MR, OR>>
+where
+    MR: MemberDataRepository + Clone + Send + Sync + 'static,
+    OR: OAuth2Repository + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/audit-log", get(get_audit_log::<MR>))
+        .route(
+            "/members/:discord_user_id/sync",
+            post(sync_member::<MR, OR>),
+        )
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditLogQuery {
+    /// Only entries of the member if given.
+    discord_user_id: Option<String>,
+    /// Only the changes moderators made through the admin commands if set.
+    #[serde(default)]
+    admin_override: bool,
+    #[serde(default = "default_audit_log_limit")]
+    limit: u32,
+}
+
+fn default_audit_log_limit() -> u32 {
+    DEFAULT_AUDIT_LOG_LIMIT
+}
+
+async fn get_audit_log<MR>(
+    State(admin_api_token): State<AdminApiToken>,
+    State(audit_log): State<AuditLogUseCase<MR>>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditLogEntry>>, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+{
+    authorize(&admin_api_token, bearer.token())?;
+
+    let entries = audit_log
+        .get_entries(
+            query.discord_user_id.as_deref(),
+            query.admin_override,
+            query.limit.min(MAX_AUDIT_LOG_LIMIT),
+        )
+        .await?;
+    Ok(Json(entries))
+}
+
+/// Fetches the member from discord right away, without waiting for the next synchronization.
+async fn sync_member<MR, OR>(
+    State(admin_api_token): State<AdminApiToken>,
+    State(members_service): State<MembersService<MR, OR>>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(discord_user_id): Path<String>,
+) -> Result<Json<MemberListRow>, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    authorize(&admin_api_token, bearer.token())?;
+
+    let member = members_service.sync_member(&discord_user_id).await?;
+    if let Some(member) = member {
+        Ok(Json(member))
+    } else {
+        Err(HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow!("the member id not found"),
+        ))
+    }
+}
+
+fn authorize(admin_api_token: &AdminApiToken, token: &str) -> Result<(), HttpError> {
+    let Some(expected) = admin_api_token.0.as_deref() else {
+        return Err(HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow!("the admin api is disabled"),
+        ));
+    };
+
+    // compared in constant time, so that the token cannot be guessed from response times.
+    let matched = expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0;
+    if matched {
+        Ok(())
+    } else {
+        tracing::warn!("admin api was requested with an invalid token");
+        Err(HttpError(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("the admin api token is invalid"),
+        ))
+    }
+}
@@ -1,32 +1,76 @@
 use anyhow::anyhow;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
 use axum::http::StatusCode;
 use axum::routing::get;
-use axum::{Json, Router};
+use axum::{Json, Router, TypedHeader};
+use serde::Deserialize;
 
-use crate::infra::repository::firestore::{MemberDataRepositoryImpl, OAuth2RepositoryImpl};
-use crate::model::MemberListRow;
+use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
+use crate::model::{MemberListRow, UnsyncedMember};
 use crate::service::members::MembersService;
+use crate::usecase::audit::Actor;
+use crate::usecase::oauth2::OAuth2UseCase;
 
 use super::{AppState, HttpError};
 
-pub(crate) fn route() -> Router<AppState> {
+pub(crate) fn route<MR, OR>() -> Router<AppState<MR, OR>>
+where
+    MR: MemberDataRepository + Clone + Send + Sync + 'static,
+    OR: OAuth2Repository + Clone + Send + Sync + 'static,
+{
     Router::new()
-        .route("/members", get(get_members))
-        .route("/members/:discord_user_id", get(get_member))
+        .route("/members", get(get_members::<MR, OR>))
+        .route("/members/unsynced", get(get_unsynced_members::<MR, OR>))
+        .route(
+            "/members/:discord_user_id",
+            get(get_member::<MR, OR>).delete(delete_member::<MR, OR>),
+        )
 }
 
-async fn get_members(
-    State(members_service): State<MembersService<MemberDataRepositoryImpl, OAuth2RepositoryImpl>>,
-) -> Result<Json<Vec<MemberListRow>>, HttpError> {
-    let members = members_service.get_all_members().await?;
+#[derive(Debug, Deserialize)]
+struct MembersQuery {
+    /// Includes members who left the guild.
+    #[serde(default)]
+    include_inactive: bool,
+}
+
+async fn get_members<MR, OR>(
+    State(members_service): State<MembersService<MR, OR>>,
+    Query(MembersQuery { include_inactive }): Query<MembersQuery>,
+) -> Result<Json<Vec<MemberListRow>>, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    let members = members_service.get_all_members(include_inactive).await?;
+    Ok(Json(members))
+}
+
+/// Registered members left out of `/members` until they are synchronized.
+async fn get_unsynced_members<MR, OR>(
+    State(members_service): State<MembersService<MR, OR>>,
+    Query(MembersQuery { include_inactive }): Query<MembersQuery>,
+) -> Result<Json<Vec<UnsyncedMember>>, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    let members = members_service
+        .get_unsynced_members(include_inactive)
+        .await?;
     Ok(Json(members))
 }
 
-async fn get_member(
-    State(members_service): State<MembersService<MemberDataRepositoryImpl, OAuth2RepositoryImpl>>,
+async fn get_member<MR, OR>(
+    State(members_service): State<MembersService<MR, OR>>,
     Path(discord_user_id): Path<String>,
-) -> Result<Json<MemberListRow>, HttpError> {
+) -> Result<Json<MemberListRow>, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
     let member = members_service.get_member(&discord_user_id).await?;
     if let Some(member) = member {
         Ok(Json(member))
@@ -37,3 +81,37 @@ async fn get_member(
         ))
     }
 }
+
+/// Only the member can delete itself, proven by an access-token issued to the member by discord.
+async fn delete_member<MR, OR>(
+    State(oauth2_usecase): State<OAuth2UseCase<MR, OR>>,
+    Path(discord_user_id): Path<String>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Result<StatusCode, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    let token_owner = oauth2_usecase
+        .get_token_owner(bearer.token())
+        .await
+        .map_err(|err| HttpError(StatusCode::UNAUTHORIZED, err))?;
+    if token_owner != discord_user_id {
+        return Err(HttpError(
+            StatusCode::FORBIDDEN,
+            anyhow!("the access-token is not issued to the member"),
+        ));
+    }
+
+    if oauth2_usecase
+        .delete_member(&discord_user_id, &Actor::Member(discord_user_id.clone()))
+        .await?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow!("the member id not found"),
+        ))
+    }
+}
@@ -1,27 +1,37 @@
 use axum::extract::{Query, State};
-use axum::response::Redirect;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::get;
 use axum::Router;
 use oauth2::CsrfToken;
 use serde::Deserialize;
 
-use crate::infra::repository::firestore::{MemberDataRepositoryImpl, OAuth2RepositoryImpl};
-use crate::usecase::oauth2::OAuth2UseCase;
+use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
+use crate::service::members::MembersService;
+use crate::usecase::oauth2::{OAuth2Error, OAuth2UseCase};
 
 use super::{AppState, HttpError};
 
 #[tracing::instrument]
-pub(crate) fn route() -> Router<AppState> {
+pub(crate) fn route<MR, OR>() -> Router<AppState<MR, OR>>
+where
+    MR: MemberDataRepository + Clone + Send + Sync + 'static,
+    OR: OAuth2Repository + Clone + Send + Sync + 'static,
+{
     Router::new()
-        .route("/discord", get(discord_auth))
-        .route("/discord/callback", get(discord_auth_callback))
+        .route("/discord", get(discord_auth::<MR, OR>))
+        .route("/discord/callback", get(discord_auth_callback::<MR, OR>))
 }
 
 #[tracing::instrument(skip(oauth2_usecase))]
-async fn discord_auth(
-    State(oauth2_usecase): State<OAuth2UseCase<MemberDataRepositoryImpl, OAuth2RepositoryImpl>>,
-) -> Result<Redirect, HttpError> {
-    let auth_url = oauth2_usecase.authenticate().await?;
+async fn discord_auth<MR, OR>(
+    State(oauth2_usecase): State<OAuth2UseCase<MR, OR>>,
+) -> Result<Redirect, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    let auth_url = oauth2_usecase.authenticate(None).await?;
 
     Ok(Redirect::to(auth_url.as_str()))
 }
@@ -32,16 +42,53 @@ struct AuthRequest {
     state: CsrfToken,
 }
 
-#[tracing::instrument(skip(oauth2_usecase, code, csrf_token))]
-async fn discord_auth_callback(
-    State(oauth2_usecase): State<OAuth2UseCase<MemberDataRepositoryImpl, OAuth2RepositoryImpl>>,
+#[tracing::instrument(skip(oauth2_usecase, members_service, code, csrf_token))]
+async fn discord_auth_callback<MR, OR>(
+    State(oauth2_usecase): State<OAuth2UseCase<MR, OR>>,
+    State(members_service): State<MembersService<MR, OR>>,
     Query(AuthRequest {
         code,
         state: csrf_token,
     }): Query<AuthRequest>,
-) -> Result<String, HttpError> {
-    oauth2_usecase
+) -> Result<Response, HttpError>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    let discord_user_id = match oauth2_usecase
         .get_token_data(csrf_token.secret().to_owned(), code)
-        .await?;
-    Ok("Successed to connect your discord account".to_string())
+        .await
+    {
+        Ok(discord_user_id) => discord_user_id,
+        Err(err) => {
+            return match err.downcast_ref::<OAuth2Error>() {
+                Some(OAuth2Error::NotGuildMember) => Ok((
+                    StatusCode::FORBIDDEN,
+                    "Your discord account was not connected because you are not a member of the guild",
+                )
+                    .into_response()),
+                Some(OAuth2Error::MissingRequiredRole) => Ok((
+                    StatusCode::FORBIDDEN,
+                    "Your discord account was not connected because you do not have any of the roles required to register",
+                )
+                    .into_response()),
+                Some(OAuth2Error::UnexpectedUser) => Ok((
+                    StatusCode::FORBIDDEN,
+                    "Your discord account was not connected because this link was issued to another user",
+                )
+                    .into_response()),
+                Some(_) => Err(HttpError(StatusCode::BAD_REQUEST, err)),
+                None => Err(err.into()),
+            };
+        }
+    };
+
+    if let Err(err) = members_service.sync_member(&discord_user_id).await {
+        tracing::warn!(
+            "could not sync member after authorization, it is synced on next tick: {}",
+            err
+        );
+    }
+
+    Ok("Successed to connect your discord account".into_response())
 }
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use crate::config::JobsConfig;
+use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
+use crate::usecase::UseCaseContainer;
+
+/// Periodically deletes expired csrf-tokens in the background.
+/// The task is dropped together with the runtime when the servers shut down.
+pub(crate) fn spawn_csrf_token_sweeper<MR, OR>(
+    usecases: Arc<UseCaseContainer<MR, OR>>,
+    config: &JobsConfig,
+) -> anyhow::Result<()>
+where
+    MR: MemberDataRepository + Clone + Send + Sync + 'static,
+    OR: OAuth2Repository + Clone + Send + Sync + 'static,
+{
+    let interval_secs = config.csrf_token_sweep_interval_secs;
+
+    tokio::spawn(async move {
+        let mut interval = interval(interval_secs);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = usecases.oauth2.sweep_expired_csrf_tokens().await {
+                tracing::warn!(
+                    "could not sweep csrf-tokens, retrying on next tick: {}",
+                    err
+                );
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Periodically fetches every member from discord and refreshes their snapshots in the background.
+/// The first synchronization runs right after startup.
+pub(crate) fn spawn_member_synchronizer<MR, OR>(
+    usecases: Arc<UseCaseContainer<MR, OR>>,
+    config: &JobsConfig,
+) -> anyhow::Result<()>
+where
+    MR: MemberDataRepository + Clone + Send + Sync + 'static,
+    OR: OAuth2Repository + Clone + Send + Sync + 'static,
+{
+    let interval_secs = config.member_sync_interval_secs;
+
+    tokio::spawn(async move {
+        let mut interval = interval(interval_secs);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = usecases.members_service.sync_all_members().await {
+                tracing::warn!("could not sync members, retrying on next tick: {}", err);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn interval(interval_secs: u64) -> Interval {
+    let mut interval = time::interval(Duration::from_secs(interval_secs));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval
+}
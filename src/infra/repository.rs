@@ -1,17 +1,27 @@
+pub(crate) mod encrypted;
 pub(crate) mod firestore;
+pub(crate) mod memory;
+pub(crate) mod migration;
+pub(crate) mod postgres;
+pub(crate) mod sqlite;
+
+use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use thiserror::Error;
 
-use crate::model::{CsrfTokenData, MemberDataRow};
+use crate::model::{
+    AppliedMigration, AuditLogEntry, CsrfTokenData, MemberDataField, MemberDataRow, MemberListRow,
+    MemberOAuth2Data, SyncFailure,
+};
 
 #[async_trait]
 pub(crate) trait MemberDataRepository {
     async fn save_oauth2_token(
         &self,
         discord_user_id: String,
-        oauth2_access_token: String,
-        oauth2_refresh_token: String,
+        oauth2: MemberOAuth2Data,
     ) -> Result<(), RepositoryError>;
 
     async fn save_display_name(
@@ -20,9 +30,122 @@ pub(crate) trait MemberDataRepository {
         new_display_name: Option<String>,
     ) -> Result<(), RepositoryError>;
 
+    /// Replaces the access and refresh tokens only if they are still the `expected` ones,
+    /// leaving every other field as is. Returns `false` if they changed or the member is gone.
+    async fn replace_oauth2_tokens(
+        &self,
+        discord_user_id: String,
+        expected: MemberOAuth2Data,
+        replacement: MemberOAuth2Data,
+    ) -> Result<bool, RepositoryError>;
+
     async fn get_member(&self, discord_user_id: &str) -> Result<MemberDataRow, RepositoryError>;
 
     async fn get_all_members(&self) -> Result<Vec<MemberDataRow>, RepositoryError>;
+
+    /// Returns up to `limit` members ordered by id, starting after the member `after`.
+    async fn get_members_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError>;
+
+    /// `Some` marks the member as having left the guild, `None` as active again.
+    /// `deactivated_by` is the moderator who marked it inactive, `None` if it left by itself.
+    async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        deactivated_by: Option<String>,
+    ) -> Result<(), RepositoryError>;
+
+    /// Deletes the member data together with its snapshot.
+    async fn delete_member(&self, discord_user_id: &str) -> Result<(), RepositoryError>;
+
+    /// Records that the member has to authorize again. Saving a new oauth2 token clears it.
+    async fn mark_needs_reauth(
+        &self,
+        discord_user_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<(), RepositoryError>;
+
+    /// `None` clears the failure after a successful synchronization.
+    async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> Result<(), RepositoryError>;
+
+    /// Snapshots of what the API serves are kept apart from the member data.
+    async fn save_member_snapshot(&self, snapshot: MemberListRow) -> Result<(), RepositoryError>;
+
+    async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<MemberListRow, RepositoryError>;
+
+    async fn get_all_member_snapshots(&self) -> Result<Vec<MemberListRow>, RepositoryError>;
+
+    /// Returns up to `limit` snapshots ordered by id, starting after the member `after`.
+    async fn get_member_snapshots_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberListRow>, RepositoryError>;
+
+    /// Entries are only ever appended, and are kept even after the member is deleted.
+    async fn append_audit_log(&self, entry: AuditLogEntry) -> Result<(), RepositoryError>;
+
+    /// Returns up to `limit` entries newest first, only of the member if `discord_user_id` is given,
+    /// and only the admin overrides if `admin_overrides_only` is set.
+    async fn get_audit_log(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError>;
+
+    /// Returns up to `limit` entries oldest first, skipping the first `offset` ones.
+    /// Entries appended meanwhile come last, so that the earlier pages stay as they were read.
+    async fn get_audit_log_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError>;
+
+    /// Returns the entries of the member recorded from `from` until before `to`.
+    async fn get_member_audit_log_between(
+        &self,
+        discord_user_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError>;
+
+    /// Overwrites every field of the members in a single transaction, creating the missing ones.
+    async fn save_members(&self, members: Vec<MemberDataRow>) -> Result<(), RepositoryError>;
+
+    async fn get_applied_migrations(&self) -> Result<Vec<AppliedMigration>, RepositoryError>;
+
+    /// Overwrites the record of the same version, if the migration ran concurrently.
+    async fn record_migration(&self, migration: AppliedMigration) -> Result<(), RepositoryError>;
+
+    /// Returns up to `limit` members below `schema_version` ordered by id,
+    /// starting after the member `after`.
+    async fn get_outdated_members_page(
+        &self,
+        schema_version: u32,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError>;
+
+    /// Writes only `fields` and the schema version of the migrated member, and only if it is
+    /// still at `read_version`. Returns `false` if it was migrated meanwhile or is gone.
+    async fn save_migrated_member(
+        &self,
+        member: MemberDataRow,
+        read_version: u32,
+        fields: &[MemberDataField],
+    ) -> Result<bool, RepositoryError>;
 }
 
 #[async_trait]
@@ -31,10 +154,229 @@ pub(crate) trait OAuth2Repository {
         &self,
         csrf_token: String,
         pkce_verifier: String,
+        expires_at: DateTime<Utc>,
+        requested_by: Option<String>,
     ) -> Result<(), RepositoryError>;
 
     async fn delete_csrf_token(&self, csrf_token: String)
         -> Result<CsrfTokenData, RepositoryError>;
+
+    /// Deletes every csrf-token which expired at `now`, returning the number of deleted rows.
+    async fn delete_expired_csrf_tokens(&self, now: DateTime<Utc>) -> Result<u64, RepositoryError>;
+
+    /// Returns every csrf-token paired with its data, including the expired ones.
+    async fn get_all_csrf_tokens(&self) -> Result<Vec<(String, CsrfTokenData)>, RepositoryError>;
+}
+
+pub(crate) type DynMemberDataRepository = Arc<dyn MemberDataRepository + Send + Sync>;
+pub(crate) type DynOAuth2Repository = Arc<dyn OAuth2Repository + Send + Sync>;
+
+#[async_trait]
+impl<T> MemberDataRepository for Arc<T>
+where
+    T: MemberDataRepository + Send + Sync + ?Sized,
+{
+    async fn save_oauth2_token(
+        &self,
+        discord_user_id: String,
+        oauth2: MemberOAuth2Data,
+    ) -> Result<(), RepositoryError> {
+        (**self).save_oauth2_token(discord_user_id, oauth2).await
+    }
+
+    async fn save_display_name(
+        &self,
+        discord_user_id: String,
+        new_display_name: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        (**self)
+            .save_display_name(discord_user_id, new_display_name)
+            .await
+    }
+
+    async fn replace_oauth2_tokens(
+        &self,
+        discord_user_id: String,
+        expected: MemberOAuth2Data,
+        replacement: MemberOAuth2Data,
+    ) -> Result<bool, RepositoryError> {
+        (**self)
+            .replace_oauth2_tokens(discord_user_id, expected, replacement)
+            .await
+    }
+
+    async fn get_member(&self, discord_user_id: &str) -> Result<MemberDataRow, RepositoryError> {
+        (**self).get_member(discord_user_id).await
+    }
+
+    async fn get_all_members(&self) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        (**self).get_all_members().await
+    }
+
+    async fn get_members_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        (**self).get_members_page(after, limit).await
+    }
+
+    async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        deactivated_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        (**self)
+            .save_inactive_since(discord_user_id, inactive_since, deactivated_by)
+            .await
+    }
+
+    async fn delete_member(&self, discord_user_id: &str) -> Result<(), RepositoryError> {
+        (**self).delete_member(discord_user_id).await
+    }
+
+    async fn mark_needs_reauth(
+        &self,
+        discord_user_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        (**self).mark_needs_reauth(discord_user_id, since).await
+    }
+
+    async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> Result<(), RepositoryError> {
+        (**self)
+            .save_sync_failure(discord_user_id, sync_failure)
+            .await
+    }
+
+    async fn save_member_snapshot(&self, snapshot: MemberListRow) -> Result<(), RepositoryError> {
+        (**self).save_member_snapshot(snapshot).await
+    }
+
+    async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<MemberListRow, RepositoryError> {
+        (**self).get_member_snapshot(discord_user_id).await
+    }
+
+    async fn get_all_member_snapshots(&self) -> Result<Vec<MemberListRow>, RepositoryError> {
+        (**self).get_all_member_snapshots().await
+    }
+
+    async fn get_member_snapshots_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberListRow>, RepositoryError> {
+        (**self).get_member_snapshots_page(after, limit).await
+    }
+
+    async fn append_audit_log(&self, entry: AuditLogEntry) -> Result<(), RepositoryError> {
+        (**self).append_audit_log(entry).await
+    }
+
+    async fn get_audit_log(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        (**self)
+            .get_audit_log(discord_user_id, admin_overrides_only, limit)
+            .await
+    }
+
+    async fn get_audit_log_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        (**self).get_audit_log_page(offset, limit).await
+    }
+
+    async fn get_member_audit_log_between(
+        &self,
+        discord_user_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        (**self)
+            .get_member_audit_log_between(discord_user_id, from, to)
+            .await
+    }
+
+    async fn save_members(&self, members: Vec<MemberDataRow>) -> Result<(), RepositoryError> {
+        (**self).save_members(members).await
+    }
+
+    async fn get_applied_migrations(&self) -> Result<Vec<AppliedMigration>, RepositoryError> {
+        (**self).get_applied_migrations().await
+    }
+
+    async fn record_migration(&self, migration: AppliedMigration) -> Result<(), RepositoryError> {
+        (**self).record_migration(migration).await
+    }
+
+    async fn get_outdated_members_page(
+        &self,
+        schema_version: u32,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        (**self)
+            .get_outdated_members_page(schema_version, after, limit)
+            .await
+    }
+
+    async fn save_migrated_member(
+        &self,
+        member: MemberDataRow,
+        read_version: u32,
+        fields: &[MemberDataField],
+    ) -> Result<bool, RepositoryError> {
+        (**self)
+            .save_migrated_member(member, read_version, fields)
+            .await
+    }
+}
+
+#[async_trait]
+impl<T> OAuth2Repository for Arc<T>
+where
+    T: OAuth2Repository + Send + Sync + ?Sized,
+{
+    async fn save_csrf_token(
+        &self,
+        csrf_token: String,
+        pkce_verifier: String,
+        expires_at: DateTime<Utc>,
+        requested_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        (**self)
+            .save_csrf_token(csrf_token, pkce_verifier, expires_at, requested_by)
+            .await
+    }
+
+    async fn delete_csrf_token(
+        &self,
+        csrf_token: String,
+    ) -> Result<CsrfTokenData, RepositoryError> {
+        (**self).delete_csrf_token(csrf_token).await
+    }
+
+    async fn delete_expired_csrf_tokens(&self, now: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        (**self).delete_expired_csrf_tokens(now).await
+    }
+
+    async fn get_all_csrf_tokens(&self) -> Result<Vec<(String, CsrfTokenData)>, RepositoryError> {
+        (**self).get_all_csrf_tokens().await
+    }
 }
 
 #[derive(Debug, Error)]
@@ -46,3 +388,9 @@ pub(crate) enum RepositoryError {
     #[error("internal error")]
     InternalError(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::InternalError(Box::new(value))
+    }
+}
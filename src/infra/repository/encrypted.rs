@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::string::FromUtf8Error;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::{DecodeError, Engine as _};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::model::{
+    AppliedMigration, AuditLogEntry, MemberDataField, MemberDataRow, MemberListRow,
+    MemberOAuth2Data, SyncFailure,
+};
+
+use super::{MemberDataRepository, RepositoryError};
+
+/// Marks a stored token as `enc:{key_id}:{base64(nonce || ciphertext)}`.
+/// Tokens without it were saved before encryption was enabled and are read as plaintext.
+const ENVELOPE_PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub(crate) enum CipherError {
+    #[error("encryption key '{0}' is not configured")]
+    UnknownKeyId(String),
+    #[error("encryption key '{0}' is invalid, expected a 256-bit key without ':' in its id")]
+    InvalidKey(String),
+    #[error("encrypted token is malformed")]
+    Malformed,
+    #[error("encrypted token is not valid base64")]
+    Base64(#[from] DecodeError),
+    #[error("decrypted token is not valid utf-8")]
+    Utf8(#[from] FromUtf8Error),
+    #[error("could not encrypt or decrypt token: {0}")]
+    Aead(aes_gcm::Error),
+}
+
+impl From<CipherError> for RepositoryError {
+    fn from(value: CipherError) -> Self {
+        Self::InternalError(Box::new(value))
+    }
+}
+
+/// AES-256-GCM keys by id. New tokens are always encrypted with the primary key,
+/// older ones stay readable as long as their key is configured.
+#[derive(Clone)]
+pub(crate) struct TokenCipher {
+    keys: Arc<HashMap<String, Aes256Gcm>>,
+    primary_key_id: String,
+}
+
+impl TokenCipher {
+    pub(crate) fn new(
+        keys: HashMap<String, Vec<u8>>,
+        primary_key_id: String,
+    ) -> Result<Self, CipherError> {
+        let keys = keys
+            .into_iter()
+            .map(|(key_id, key)| {
+                if key_id.contains(':') {
+                    return Err(CipherError::InvalidKey(key_id));
+                }
+                match Aes256Gcm::new_from_slice(&key) {
+                    Ok(cipher) => Ok((key_id, cipher)),
+                    Err(_invalid_length) => Err(CipherError::InvalidKey(key_id)),
+                }
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        if !keys.contains_key(&primary_key_id) {
+            return Err(CipherError::UnknownKeyId(primary_key_id));
+        }
+
+        Ok(Self {
+            keys: Arc::new(keys),
+            primary_key_id,
+        })
+    }
+
+    pub(crate) fn encrypt(&self, plaintext: &str, aad: &str) -> Result<String, CipherError> {
+        let cipher = self
+            .keys
+            .get(&self.primary_key_id)
+            .ok_or_else(|| CipherError::UnknownKeyId(self.primary_key_id.clone()))?;
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(CipherError::Aead)?;
+
+        Ok(format!(
+            "{ENVELOPE_PREFIX}{}:{}",
+            self.primary_key_id,
+            STANDARD.encode([nonce.as_slice(), &ciphertext].concat())
+        ))
+    }
+
+    pub(crate) fn decrypt(&self, stored: &str, aad: &str) -> Result<String, CipherError> {
+        let Some(envelope) = stored.strip_prefix(ENVELOPE_PREFIX) else {
+            return Ok(stored.to_owned());
+        };
+
+        let (key_id, payload) = envelope.split_once(':').ok_or(CipherError::Malformed)?;
+        let cipher = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| CipherError::UnknownKeyId(key_id.to_owned()))?;
+
+        let bytes = STANDARD.decode(payload)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(CipherError::Malformed);
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(CipherError::Aead)?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+/// Whether the token was stored encrypted, rather than saved before encryption was enabled.
+pub(crate) fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENVELOPE_PREFIX)
+}
+
+/// Binds a ciphertext to its owner and field, so it cannot be copied into another row.
+pub(crate) fn associated_data(discord_user_id: &str, field: &str) -> String {
+    format!("{discord_user_id}/{field}")
+}
+
+/// Wraps another repository and keeps the oauth2 tokens encrypted at rest.
+#[derive(Clone)]
+pub(crate) struct MemberDataRepositoryImpl<R> {
+    inner: R,
+    cipher: TokenCipher,
+}
+
+impl<R> MemberDataRepositoryImpl<R> {
+    pub(crate) fn new(inner: R, cipher: TokenCipher) -> Self {
+        Self { inner, cipher }
+    }
+
+    fn encrypt_oauth2(
+        &self,
+        discord_user_id: &str,
+        mut oauth2: MemberOAuth2Data,
+    ) -> Result<MemberOAuth2Data, RepositoryError> {
+        oauth2.access_token = self.cipher.encrypt(
+            &oauth2.access_token,
+            &associated_data(discord_user_id, "access_token"),
+        )?;
+        oauth2.refresh_token = self.cipher.encrypt(
+            &oauth2.refresh_token,
+            &associated_data(discord_user_id, "refresh_token"),
+        )?;
+
+        Ok(oauth2)
+    }
+
+    fn decrypt_row(&self, mut row: MemberDataRow) -> Result<MemberDataRow, RepositoryError> {
+        row.oauth2.access_token = self.cipher.decrypt(
+            &row.oauth2.access_token,
+            &associated_data(&row.discord_user_id, "access_token"),
+        )?;
+        row.oauth2.refresh_token = self.cipher.decrypt(
+            &row.oauth2.refresh_token,
+            &associated_data(&row.discord_user_id, "refresh_token"),
+        )?;
+
+        Ok(row)
+    }
+}
+
+#[async_trait]
+impl<R> MemberDataRepository for MemberDataRepositoryImpl<R>
+where
+    R: MemberDataRepository + Send + Sync,
+{
+    async fn save_oauth2_token(
+        &self,
+        discord_user_id: String,
+        oauth2: MemberOAuth2Data,
+    ) -> Result<(), RepositoryError> {
+        let oauth2 = self.encrypt_oauth2(&discord_user_id, oauth2)?;
+        self.inner.save_oauth2_token(discord_user_id, oauth2).await
+    }
+
+    async fn save_display_name(
+        &self,
+        discord_user_id: String,
+        new_display_name: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        self.inner
+            .save_display_name(discord_user_id, new_display_name)
+            .await
+    }
+
+    async fn replace_oauth2_tokens(
+        &self,
+        discord_user_id: String,
+        expected: MemberOAuth2Data,
+        replacement: MemberOAuth2Data,
+    ) -> Result<bool, RepositoryError> {
+        // the stored ciphertext is what the inner repository compares against.
+        let stored = match self.inner.get_member(&discord_user_id).await {
+            Err(RepositoryError::NotFound { .. }) => return Ok(false),
+            result => result?,
+        };
+        let current = self.decrypt_row(stored.clone())?;
+        if current.oauth2.access_token != expected.access_token
+            || current.oauth2.refresh_token != expected.refresh_token
+        {
+            return Ok(false);
+        }
+
+        let replacement = self.encrypt_oauth2(&discord_user_id, replacement)?;
+        self.inner
+            .replace_oauth2_tokens(discord_user_id, stored.oauth2, replacement)
+            .await
+    }
+
+    async fn get_member(&self, discord_user_id: &str) -> Result<MemberDataRow, RepositoryError> {
+        let row = self.inner.get_member(discord_user_id).await?;
+        self.decrypt_row(row)
+    }
+
+    async fn get_all_members(&self) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        self.inner
+            .get_all_members()
+            .await?
+            .into_iter()
+            .map(|row| self.decrypt_row(row))
+            .collect()
+    }
+
+    async fn get_members_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        self.inner
+            .get_members_page(after, limit)
+            .await?
+            .into_iter()
+            .map(|row| self.decrypt_row(row))
+            .collect()
+    }
+
+    async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        deactivated_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        self.inner
+            .save_inactive_since(discord_user_id, inactive_since, deactivated_by)
+            .await
+    }
+
+    async fn delete_member(&self, discord_user_id: &str) -> Result<(), RepositoryError> {
+        self.inner.delete_member(discord_user_id).await
+    }
+
+    async fn mark_needs_reauth(
+        &self,
+        discord_user_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        self.inner.mark_needs_reauth(discord_user_id, since).await
+    }
+
+    async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> Result<(), RepositoryError> {
+        self.inner
+            .save_sync_failure(discord_user_id, sync_failure)
+            .await
+    }
+
+    async fn save_member_snapshot(&self, snapshot: MemberListRow) -> Result<(), RepositoryError> {
+        self.inner.save_member_snapshot(snapshot).await
+    }
+
+    async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<MemberListRow, RepositoryError> {
+        self.inner.get_member_snapshot(discord_user_id).await
+    }
+
+    async fn get_all_member_snapshots(&self) -> Result<Vec<MemberListRow>, RepositoryError> {
+        self.inner.get_all_member_snapshots().await
+    }
+
+    async fn get_member_snapshots_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberListRow>, RepositoryError> {
+        self.inner.get_member_snapshots_page(after, limit).await
+    }
+
+    async fn append_audit_log(&self, entry: AuditLogEntry) -> Result<(), RepositoryError> {
+        self.inner.append_audit_log(entry).await
+    }
+
+    async fn get_audit_log(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        self.inner
+            .get_audit_log(discord_user_id, admin_overrides_only, limit)
+            .await
+    }
+
+    async fn get_audit_log_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        self.inner.get_audit_log_page(offset, limit).await
+    }
+
+    async fn get_member_audit_log_between(
+        &self,
+        discord_user_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        self.inner
+            .get_member_audit_log_between(discord_user_id, from, to)
+            .await
+    }
+
+    async fn save_members(&self, members: Vec<MemberDataRow>) -> Result<(), RepositoryError> {
+        let members = members
+            .into_iter()
+            .map(|mut member| {
+                member.oauth2 = self.encrypt_oauth2(&member.discord_user_id, member.oauth2)?;
+                Ok(member)
+            })
+            .collect::<Result<Vec<_>, RepositoryError>>()?;
+
+        self.inner.save_members(members).await
+    }
+
+    async fn get_applied_migrations(&self) -> Result<Vec<AppliedMigration>, RepositoryError> {
+        self.inner.get_applied_migrations().await
+    }
+
+    async fn record_migration(&self, migration: AppliedMigration) -> Result<(), RepositoryError> {
+        self.inner.record_migration(migration).await
+    }
+
+    async fn get_outdated_members_page(
+        &self,
+        schema_version: u32,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        self.inner
+            .get_outdated_members_page(schema_version, after, limit)
+            .await?
+            .into_iter()
+            .map(|row| self.decrypt_row(row))
+            .collect()
+    }
+
+    /// The tokens are never among the migrated fields, so they are passed through as they are.
+    async fn save_migrated_member(
+        &self,
+        member: MemberDataRow,
+        read_version: u32,
+        fields: &[MemberDataField],
+    ) -> Result<bool, RepositoryError> {
+        self.inner
+            .save_migrated_member(member, read_version, fields)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::infra::repository::{memory, MemberDataRepository};
+    use crate::model::MemberOAuth2Data;
+
+    use super::{MemberDataRepositoryImpl, TokenCipher, ENVELOPE_PREFIX};
+
+    const MEMBER_ID: &str = "100";
+
+    fn cipher(key_ids: &[&str], primary_key_id: &str) -> anyhow::Result<TokenCipher> {
+        // derived from the id, so that a key stays the same across the ciphers of a test.
+        let keys = key_ids
+            .iter()
+            .map(|key_id| {
+                let key = key_id.bytes().cycle().take(32).collect::<Vec<_>>();
+                ((*key_id).to_owned(), key)
+            })
+            .collect::<HashMap<_, _>>();
+
+        Ok(TokenCipher::new(keys, primary_key_id.to_owned())?)
+    }
+
+    fn oauth2(access_token: &str, refresh_token: &str) -> MemberOAuth2Data {
+        MemberOAuth2Data {
+            access_token: access_token.to_owned(),
+            refresh_token: refresh_token.to_owned(),
+            expires_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn tokens_are_stored_encrypted_and_read_as_plaintext() -> anyhow::Result<()> {
+        let inner = memory::MemberDataRepositoryImpl::new();
+        let repository = MemberDataRepositoryImpl::new(inner.clone(), cipher(&["old"], "old")?);
+
+        repository
+            .save_oauth2_token(MEMBER_ID.to_owned(), oauth2("access", "refresh"))
+            .await?;
+
+        let stored = inner.get_member(MEMBER_ID).await?;
+        assert!(stored.oauth2.access_token.starts_with("enc:old:"));
+        assert!(stored.oauth2.refresh_token.starts_with("enc:old:"));
+        let member = repository.get_member(MEMBER_ID).await?;
+        assert_eq!(member.oauth2.access_token, "access");
+        assert_eq!(member.oauth2.refresh_token, "refresh");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rotated_tokens_are_readable_without_the_old_key() -> anyhow::Result<()> {
+        let inner = memory::MemberDataRepositoryImpl::new();
+        MemberDataRepositoryImpl::new(inner.clone(), cipher(&["old"], "old")?)
+            .save_oauth2_token(MEMBER_ID.to_owned(), oauth2("access", "refresh"))
+            .await?;
+
+        let rotating =
+            MemberDataRepositoryImpl::new(inner.clone(), cipher(&["old", "new"], "new")?);
+        let member = rotating.get_member(MEMBER_ID).await?;
+        assert!(
+            rotating
+                .replace_oauth2_tokens(MEMBER_ID.to_owned(), member.oauth2.clone(), member.oauth2,)
+                .await?
+        );
+
+        let stored = inner.get_member(MEMBER_ID).await?;
+        assert!(stored.oauth2.access_token.starts_with("enc:new:"));
+        assert!(stored.oauth2.refresh_token.starts_with("enc:new:"));
+        let rotated = MemberDataRepositoryImpl::new(inner, cipher(&["new"], "new")?);
+        let rotated_member = rotated.get_member(MEMBER_ID).await?;
+        assert_eq!(rotated_member.oauth2.access_token, "access");
+        assert_eq!(rotated_member.oauth2.refresh_token, "refresh");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tokens_changed_meanwhile_are_not_replaced() -> anyhow::Result<()> {
+        let inner = memory::MemberDataRepositoryImpl::new();
+        let repository = MemberDataRepositoryImpl::new(inner, cipher(&["old", "new"], "new")?);
+        repository
+            .save_oauth2_token(MEMBER_ID.to_owned(), oauth2("access", "refresh"))
+            .await?;
+
+        assert!(
+            !repository
+                .replace_oauth2_tokens(
+                    MEMBER_ID.to_owned(),
+                    oauth2("outdated", "refresh"),
+                    oauth2("replacement", "refresh"),
+                )
+                .await?
+        );
+        assert!(
+            !repository
+                .replace_oauth2_tokens(
+                    "200".to_owned(),
+                    oauth2("access", "refresh"),
+                    oauth2("replacement", "refresh"),
+                )
+                .await?
+        );
+        assert_eq!(
+            repository.get_member(MEMBER_ID).await?.oauth2.access_token,
+            "access"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::panic_in_result_fn)]
+    fn plaintext_saved_before_encryption_is_read_as_is() -> anyhow::Result<()> {
+        let cipher = cipher(&["old"], "old")?;
+
+        assert_eq!(cipher.decrypt("access", "100/access_token")?, "access");
+        let encrypted = cipher.encrypt("access", "100/access_token")?;
+        assert!(encrypted.starts_with(ENVELOPE_PREFIX));
+        // the ciphertext is bound to its owner and field.
+        assert!(cipher.decrypt(&encrypted, "200/access_token").is_err());
+
+        Ok(())
+    }
+}
@@ -1,24 +1,42 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use firestore::{paths, struct_path, FirestoreDb};
+use chrono::{DateTime, Utc};
+use firestore::{
+    path, paths, struct_path, FirestoreDb, FirestoreQueryDirection, FirestoreTimestamp,
+};
 use futures_util::StreamExt as _;
 use tokio::sync::Mutex;
 
 use crate::infra::repository::{MemberDataRepository, RepositoryError};
-use crate::model::{MemberDataRow, MemberOAuth2Data};
+use crate::model::{
+    AppliedMigration, AuditLogEntry, MemberDataField, MemberDataRow, MemberListRow,
+    MemberOAuth2Data, SyncFailure, MEMBER_DATA_SCHEMA_VERSION,
+};
 
 #[derive(Clone)]
 pub(crate) struct MemberDataRepositoryImpl {
     db: Arc<Mutex<FirestoreDb>>,
-    collection_name: &'static str,
+    collection_name: String,
+    snapshot_collection_name: String,
+    audit_log_collection_name: String,
+    migrations_collection_name: String,
 }
 
 impl MemberDataRepositoryImpl {
-    pub(crate) fn new(db: Arc<Mutex<FirestoreDb>>, collection_name: &'static str) -> Self {
+    pub(crate) fn new(
+        db: Arc<Mutex<FirestoreDb>>,
+        collection_name: String,
+        snapshot_collection_name: String,
+        audit_log_collection_name: String,
+        migrations_collection_name: String,
+    ) -> Self {
         Self {
             db,
             collection_name,
+            snapshot_collection_name,
+            audit_log_collection_name,
+            migrations_collection_name,
         }
     }
 }
@@ -28,29 +46,63 @@ impl MemberDataRepository for MemberDataRepositoryImpl {
     async fn save_oauth2_token(
         &self,
         discord_user_id: String,
-        oauth2_access_token: String,
-        oauth2_refresh_token: String,
+        oauth2: MemberOAuth2Data,
     ) -> Result<(), RepositoryError> {
         let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+        let now = Utc::now();
+
+        let existing: Option<MemberDataRow> = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(&discord_user_id)
+            .await?;
 
         let data = MemberDataRow {
             discord_user_id: discord_user_id.clone(),
             display_name: None,
-            oauth2: MemberOAuth2Data {
-                access_token: oauth2_access_token,
-                refresh_token: oauth2_refresh_token,
-            },
+            oauth2,
+            sync_failure: None,
+            needs_reauth_since: None,
+            inactive_since: None,
+            registered_at: Some(now),
+            updated_at: Some(now),
+            last_token_refresh_at: Some(now),
+            deactivated_by: None,
+            schema_version: MEMBER_DATA_SCHEMA_VERSION,
+        };
+        // members registered before keep their registration date, even when it is unknown,
+        // and their schema version, which only migrations bring up.
+        let fields = match existing {
+            Some(_) => paths!(MemberDataRow::{
+                discord_user_id,
+                oauth2,
+                needs_reauth_since,
+                updated_at,
+                last_token_refresh_at
+            }),
+            None => paths!(MemberDataRow::{
+                discord_user_id,
+                oauth2,
+                needs_reauth_since,
+                registered_at,
+                updated_at,
+                last_token_refresh_at,
+                schema_version
+            }),
         };
 
         db.fluent()
             .update()
-            .fields(paths!(MemberDataRow::{discord_user_id, oauth2}))
-            .in_col(self.collection_name)
+            .fields(fields)
+            .in_col(self.collection_name.as_str())
             .document_id(&discord_user_id)
             .object(&data)
-            .execute()
-            .await?;
+            .add_to_transaction(&mut transaction)?;
 
+        transaction.commit().await?;
         Ok(())
     }
 
@@ -65,7 +117,7 @@ impl MemberDataRepository for MemberDataRepositoryImpl {
         let mut user_data: MemberDataRow = db
             .fluent()
             .select()
-            .by_id_in(self.collection_name)
+            .by_id_in(self.collection_name.as_str())
             .obj()
             .one(&discord_user_id)
             .await?
@@ -74,11 +126,12 @@ impl MemberDataRepository for MemberDataRepositoryImpl {
             })?;
 
         user_data.display_name = new_display_name;
+        user_data.updated_at = Some(Utc::now());
 
         db.fluent()
             .update()
-            .fields(paths!(MemberDataRow::display_name))
-            .in_col(self.collection_name)
+            .fields(paths!(MemberDataRow::{display_name, updated_at}))
+            .in_col(self.collection_name.as_str())
             .document_id(&discord_user_id)
             .object(&user_data)
             .add_to_transaction(&mut transaction)?;
@@ -87,12 +140,53 @@ impl MemberDataRepository for MemberDataRepositoryImpl {
         Ok(())
     }
 
+    async fn replace_oauth2_tokens(
+        &self,
+        discord_user_id: String,
+        expected: MemberOAuth2Data,
+        replacement: MemberOAuth2Data,
+    ) -> Result<bool, RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        let user_data: Option<MemberDataRow> = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(&discord_user_id)
+            .await?;
+        let Some(mut user_data) = user_data else {
+            return Ok(false);
+        };
+        if user_data.oauth2.access_token != expected.access_token
+            || user_data.oauth2.refresh_token != expected.refresh_token
+        {
+            return Ok(false);
+        }
+
+        // the expiry is written back as read, which matches the tokens compared above.
+        user_data.oauth2.access_token = replacement.access_token;
+        user_data.oauth2.refresh_token = replacement.refresh_token;
+
+        db.fluent()
+            .update()
+            .fields(paths!(MemberDataRow::oauth2))
+            .in_col(self.collection_name.as_str())
+            .document_id(&discord_user_id)
+            .object(&user_data)
+            .add_to_transaction(&mut transaction)?;
+
+        transaction.commit().await?;
+        Ok(true)
+    }
+
     async fn get_member(&self, discord_user_id: &str) -> Result<MemberDataRow, RepositoryError> {
         let db = self.db.lock().await;
 
         db.fluent()
             .select()
-            .by_id_in(self.collection_name)
+            .by_id_in(self.collection_name.as_str())
             .obj()
             .one(discord_user_id)
             .await?
@@ -107,13 +201,489 @@ impl MemberDataRepository for MemberDataRepositoryImpl {
         let member_data: Vec<MemberDataRow> = db
             .fluent()
             .list()
-            .from(self.collection_name)
+            .from(self.collection_name.as_str())
+            .obj()
+            .stream_all()
+            .await?
+            .collect()
+            .await;
+
+        Ok(member_data)
+    }
+
+    async fn get_members_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let member_data: Vec<MemberDataRow> = db
+            .fluent()
+            .select()
+            .from(self.collection_name.as_str())
+            .filter(|q| {
+                q.for_all([after.and_then(|after_id| {
+                    q.field(path!(MemberDataRow::discord_user_id))
+                        .greater_than(after_id)
+                })])
+            })
+            .order_by([(
+                path!(MemberDataRow::discord_user_id),
+                FirestoreQueryDirection::Ascending,
+            )])
+            .limit(limit)
+            .obj()
+            .query()
+            .await?;
+
+        Ok(member_data)
+    }
+
+    async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        deactivated_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        let mut user_data: MemberDataRow = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(&discord_user_id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.clone(),
+            })?;
+
+        user_data.inactive_since = inactive_since;
+        user_data.deactivated_by = deactivated_by;
+
+        db.fluent()
+            .update()
+            .fields(paths!(MemberDataRow::{inactive_since, deactivated_by}))
+            .in_col(self.collection_name.as_str())
+            .document_id(&discord_user_id)
+            .object(&user_data)
+            .add_to_transaction(&mut transaction)?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_member(&self, discord_user_id: &str) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        let _member: MemberDataRow = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(discord_user_id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.to_owned(),
+            })?;
+
+        db.fluent()
+            .delete()
+            .from(self.collection_name.as_str())
+            .document_id(discord_user_id)
+            .add_to_transaction(&mut transaction)?;
+        db.fluent()
+            .delete()
+            .from(self.snapshot_collection_name.as_str())
+            .document_id(discord_user_id)
+            .add_to_transaction(&mut transaction)?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_needs_reauth(
+        &self,
+        discord_user_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        let mut user_data: MemberDataRow = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(&discord_user_id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.clone(),
+            })?;
+
+        user_data.needs_reauth_since = Some(since);
+
+        db.fluent()
+            .update()
+            .fields(paths!(MemberDataRow::needs_reauth_since))
+            .in_col(self.collection_name.as_str())
+            .document_id(&discord_user_id)
+            .object(&user_data)
+            .add_to_transaction(&mut transaction)?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        let mut user_data: MemberDataRow = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(&discord_user_id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.clone(),
+            })?;
+
+        user_data.sync_failure = sync_failure;
+
+        db.fluent()
+            .update()
+            .fields(paths!(MemberDataRow::sync_failure))
+            .in_col(self.collection_name.as_str())
+            .document_id(&discord_user_id)
+            .object(&user_data)
+            .add_to_transaction(&mut transaction)?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn save_member_snapshot(&self, snapshot: MemberListRow) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+
+        db.fluent()
+            .update()
+            .in_col(self.snapshot_collection_name.as_str())
+            .document_id(&snapshot.discord_user_id)
+            .object(&snapshot)
+            .execute::<MemberListRow>()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<MemberListRow, RepositoryError> {
+        let db = self.db.lock().await;
+
+        db.fluent()
+            .select()
+            .by_id_in(self.snapshot_collection_name.as_str())
+            .obj()
+            .one(discord_user_id)
+            .await?
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.to_owned(),
+            })
+    }
+
+    async fn get_all_member_snapshots(&self) -> Result<Vec<MemberListRow>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let snapshots: Vec<MemberListRow> = db
+            .fluent()
+            .list()
+            .from(self.snapshot_collection_name.as_str())
+            .obj()
+            .stream_all()
+            .await?
+            .collect()
+            .await;
+
+        Ok(snapshots)
+    }
+
+    async fn get_member_snapshots_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberListRow>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let snapshots: Vec<MemberListRow> = db
+            .fluent()
+            .select()
+            .from(self.snapshot_collection_name.as_str())
+            .filter(|q| {
+                q.for_all([after.and_then(|after_id| {
+                    q.field(path!(MemberListRow::discord_user_id))
+                        .greater_than(after_id)
+                })])
+            })
+            .order_by([(
+                path!(MemberListRow::discord_user_id),
+                FirestoreQueryDirection::Ascending,
+            )])
+            .limit(limit)
+            .obj()
+            .query()
+            .await?;
+
+        Ok(snapshots)
+    }
+
+    async fn append_audit_log(&self, entry: AuditLogEntry) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+
+        db.fluent()
+            .insert()
+            .into(self.audit_log_collection_name.as_str())
+            .generate_document_id()
+            .object(&entry)
+            .execute::<AuditLogEntry>()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Filtering by the member needs a composite index on `discord_user_id` and `recorded_at`.
+    /// Entries recorded before overrides were flagged lack the field, and are not matched as such.
+    async fn get_audit_log(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let entries: Vec<AuditLogEntry> = db
+            .fluent()
+            .select()
+            .from(self.audit_log_collection_name.as_str())
+            .filter(|q| {
+                q.for_all([
+                    discord_user_id
+                        .and_then(|id| q.field(path!(AuditLogEntry::discord_user_id)).eq(id)),
+                    admin_overrides_only
+                        .then(|| q.field(path!(AuditLogEntry::admin_override)).eq(true))
+                        .flatten(),
+                ])
+            })
+            .order_by([(
+                path!(AuditLogEntry::recorded_at),
+                FirestoreQueryDirection::Descending,
+            )])
+            .limit(limit)
+            .obj()
+            .query()
+            .await?;
+
+        Ok(entries)
+    }
+
+    async fn get_audit_log_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let entries: Vec<AuditLogEntry> = db
+            .fluent()
+            .select()
+            .from(self.audit_log_collection_name.as_str())
+            .order_by([(
+                path!(AuditLogEntry::recorded_at),
+                FirestoreQueryDirection::Ascending,
+            )])
+            .offset(offset)
+            .limit(limit)
+            .obj()
+            .query()
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// Needs the same composite index on `discord_user_id` and `recorded_at` as `get_audit_log`.
+    async fn get_member_audit_log_between(
+        &self,
+        discord_user_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let entries: Vec<AuditLogEntry> = db
+            .fluent()
+            .select()
+            .from(self.audit_log_collection_name.as_str())
+            .filter(|q| {
+                q.for_all([
+                    q.field(path!(AuditLogEntry::discord_user_id))
+                        .eq(discord_user_id),
+                    q.field(path!(AuditLogEntry::recorded_at))
+                        .greater_than_or_equal(FirestoreTimestamp(from)),
+                    q.field(path!(AuditLogEntry::recorded_at))
+                        .less_than(FirestoreTimestamp(to)),
+                ])
+            })
+            .order_by([(
+                path!(AuditLogEntry::recorded_at),
+                FirestoreQueryDirection::Ascending,
+            )])
+            .obj()
+            .query()
+            .await?;
+
+        Ok(entries)
+    }
+
+    async fn save_members(&self, members: Vec<MemberDataRow>) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        for member in &members {
+            db.fluent()
+                .update()
+                .in_col(self.collection_name.as_str())
+                .document_id(&member.discord_user_id)
+                .object(member)
+                .add_to_transaction(&mut transaction)?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn get_applied_migrations(&self) -> Result<Vec<AppliedMigration>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let migrations: Vec<AppliedMigration> = db
+            .fluent()
+            .list()
+            .from(self.migrations_collection_name.as_str())
             .obj()
             .stream_all()
             .await?
             .collect()
             .await;
 
+        Ok(migrations)
+    }
+
+    async fn record_migration(&self, migration: AppliedMigration) -> Result<(), RepositoryError> {
+        let db = self.db.lock().await;
+
+        db.fluent()
+            .update()
+            .in_col(self.migrations_collection_name.as_str())
+            .document_id(migration.version.to_string())
+            .object(&migration)
+            .execute::<AppliedMigration>()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Documents saved before the schema version was recorded lack the field and are not matched,
+    /// they are read as version 0, which the migrations so far leave as is.
+    /// Needs a composite index on `schema_version` and `discord_user_id`.
+    async fn get_outdated_members_page(
+        &self,
+        schema_version: u32,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let db = self.db.lock().await;
+        let outdated_versions = (0..schema_version).collect::<Vec<_>>();
+
+        let member_data: Vec<MemberDataRow> = db
+            .fluent()
+            .select()
+            .from(self.collection_name.as_str())
+            .filter(|q| {
+                q.for_all([
+                    q.field(path!(MemberDataRow::schema_version))
+                        .is_in(outdated_versions.clone()),
+                    after.and_then(|after_id| {
+                        q.field(path!(MemberDataRow::discord_user_id))
+                            .greater_than(after_id)
+                    }),
+                ])
+            })
+            .order_by([(
+                path!(MemberDataRow::discord_user_id),
+                FirestoreQueryDirection::Ascending,
+            )])
+            .limit(limit)
+            .obj()
+            .query()
+            .await?;
+
         Ok(member_data)
     }
+
+    async fn save_migrated_member(
+        &self,
+        member: MemberDataRow,
+        read_version: u32,
+        fields: &[MemberDataField],
+    ) -> Result<bool, RepositoryError> {
+        let db = self.db.lock().await;
+        let mut transaction = db.begin_transaction().await?;
+
+        let user_data: Option<MemberDataRow> = db
+            .fluent()
+            .select()
+            .by_id_in(self.collection_name.as_str())
+            .obj()
+            .one(&member.discord_user_id)
+            .await?;
+        if user_data.map_or(true, |current| current.schema_version != read_version) {
+            return Ok(false);
+        }
+
+        let mut update_fields = paths!(MemberDataRow::schema_version);
+        for field in fields {
+            update_fields.extend(match field {
+                MemberDataField::DisplayName => paths!(MemberDataRow::display_name),
+                MemberDataField::SyncFailure => paths!(MemberDataRow::sync_failure),
+                MemberDataField::NeedsReauthSince => paths!(MemberDataRow::needs_reauth_since),
+                MemberDataField::InactiveSince => paths!(MemberDataRow::inactive_since),
+                MemberDataField::RegisteredAt => paths!(MemberDataRow::registered_at),
+                MemberDataField::UpdatedAt => paths!(MemberDataRow::updated_at),
+                MemberDataField::LastTokenRefreshAt => {
+                    paths!(MemberDataRow::last_token_refresh_at)
+                }
+                MemberDataField::DeactivatedBy => paths!(MemberDataRow::deactivated_by),
+            });
+        }
+
+        db.fluent()
+            .update()
+            .fields(update_fields)
+            .in_col(self.collection_name.as_str())
+            .document_id(&member.discord_user_id)
+            .object(&member)
+            .add_to_transaction(&mut transaction)?;
+
+        transaction.commit().await?;
+        Ok(true)
+    }
 }
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use chrono::{Duration, Utc};
-use firestore::{paths, struct_path, FirestoreDb};
+use chrono::{DateTime, Utc};
+use firestore::{path, paths, struct_path, FirestoreDb, FirestoreTimestamp};
 use tokio::sync::Mutex;
 
 use crate::infra::repository::{OAuth2Repository, RepositoryError};
@@ -11,11 +11,11 @@ use crate::model::CsrfTokenData;
 #[derive(Clone)]
 pub(crate) struct OAuth2RepositoryImpl {
     db: Arc<Mutex<FirestoreDb>>,
-    collection_name: &'static str,
+    collection_name: String,
 }
 
 impl OAuth2RepositoryImpl {
-    pub(crate) fn new(db: Arc<Mutex<FirestoreDb>>, collection_name: &'static str) -> Self {
+    pub(crate) fn new(db: Arc<Mutex<FirestoreDb>>, collection_name: String) -> Self {
         Self {
             db,
             collection_name,
@@ -29,18 +29,21 @@ impl OAuth2Repository for OAuth2RepositoryImpl {
         &self,
         csrf_token: String,
         pkce_verifier: String,
+        expires_at: DateTime<Utc>,
+        requested_by: Option<String>,
     ) -> Result<(), RepositoryError> {
         let db = self.db.lock().await;
 
         let data = CsrfTokenData {
             pkce_verifier,
-            expires_at: Utc::now() + Duration::days(1),
+            expires_at,
+            requested_by,
         };
 
         db.fluent()
             .update()
-            .fields(paths!(CsrfTokenData::{pkce_verifier, expires_at}))
-            .in_col(self.collection_name)
+            .fields(paths!(CsrfTokenData::{pkce_verifier, expires_at, requested_by}))
+            .in_col(self.collection_name.as_str())
             .document_id(&csrf_token)
             .object(&data)
             .execute::<CsrfTokenData>()
@@ -59,7 +62,7 @@ impl OAuth2Repository for OAuth2RepositoryImpl {
         let data: CsrfTokenData = db
             .fluent()
             .select()
-            .by_id_in(self.collection_name)
+            .by_id_in(self.collection_name.as_str())
             .obj()
             .one(&csrf_token)
             .await?
@@ -69,7 +72,7 @@ impl OAuth2Repository for OAuth2RepositoryImpl {
 
         db.fluent()
             .delete()
-            .from(self.collection_name)
+            .from(self.collection_name.as_str())
             .document_id(&csrf_token)
             .add_to_transaction(&mut transaction)?;
 
@@ -77,4 +80,61 @@ impl OAuth2Repository for OAuth2RepositoryImpl {
 
         Ok(data)
     }
+
+    async fn delete_expired_csrf_tokens(&self, now: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let expired_documents = db
+            .fluent()
+            .select()
+            .from(self.collection_name.as_str())
+            .filter(|q| {
+                q.for_all([q
+                    .field(path!(CsrfTokenData::expires_at))
+                    .less_than_or_equal(FirestoreTimestamp(now))])
+            })
+            .query()
+            .await?;
+
+        let mut deleted = 0;
+        for document in expired_documents {
+            // the document name is `.../documents/{collection}/{csrf_token}`
+            let Some(csrf_token) = document.name.rsplit('/').next() else {
+                continue;
+            };
+
+            db.fluent()
+                .delete()
+                .from(self.collection_name.as_str())
+                .document_id(csrf_token)
+                .execute()
+                .await?;
+            deleted += 1;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn get_all_csrf_tokens(&self) -> Result<Vec<(String, CsrfTokenData)>, RepositoryError> {
+        let db = self.db.lock().await;
+
+        let documents = db
+            .fluent()
+            .select()
+            .from(self.collection_name.as_str())
+            .query()
+            .await?;
+
+        let mut csrf_tokens = Vec::with_capacity(documents.len());
+        for document in documents {
+            // the document name is `.../documents/{collection}/{csrf_token}`
+            let Some(csrf_token) = document.name.rsplit('/').next() else {
+                continue;
+            };
+            let data = FirestoreDb::deserialize_doc_to::<CsrfTokenData>(&document)?;
+            csrf_tokens.push((csrf_token.to_owned(), data));
+        }
+
+        Ok(csrf_tokens)
+    }
 }
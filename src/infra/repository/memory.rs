@@ -0,0 +1,5 @@
+mod members;
+mod oauth2;
+
+pub(crate) use self::oauth2::OAuth2RepositoryImpl;
+pub(crate) use members::MemberDataRepositoryImpl;
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::infra::repository::{MemberDataRepository, RepositoryError};
+use crate::model::{
+    AppliedMigration, AuditLogEntry, MemberDataField, MemberDataRow, MemberListRow,
+    MemberOAuth2Data, SyncFailure, MEMBER_DATA_SCHEMA_VERSION,
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct MemberDataRepositoryImpl {
+    rows: Arc<Mutex<HashMap<String, MemberDataRow>>>,
+    snapshots: Arc<Mutex<HashMap<String, MemberListRow>>>,
+    audit_log: Arc<Mutex<Vec<AuditLogEntry>>>,
+    migrations: Arc<Mutex<HashMap<u32, AppliedMigration>>>,
+}
+
+impl MemberDataRepositoryImpl {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MemberDataRepository for MemberDataRepositoryImpl {
+    async fn save_oauth2_token(
+        &self,
+        discord_user_id: String,
+        oauth2: MemberOAuth2Data,
+    ) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+        let now = Utc::now();
+
+        rows.entry(discord_user_id.clone())
+            .and_modify(|row| {
+                row.oauth2 = oauth2.clone();
+                row.needs_reauth_since = None;
+                row.updated_at = Some(now);
+                row.last_token_refresh_at = Some(now);
+            })
+            .or_insert_with(|| MemberDataRow {
+                discord_user_id,
+                display_name: None,
+                oauth2,
+                sync_failure: None,
+                needs_reauth_since: None,
+                inactive_since: None,
+                registered_at: Some(now),
+                updated_at: Some(now),
+                last_token_refresh_at: Some(now),
+                deactivated_by: None,
+                schema_version: MEMBER_DATA_SCHEMA_VERSION,
+            });
+
+        Ok(())
+    }
+
+    async fn save_display_name(
+        &self,
+        discord_user_id: String,
+        new_display_name: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        // holding the lock for the whole read-modify-write plays the role of the transaction.
+        let mut rows = self.rows.lock().await;
+
+        let user_data =
+            rows.get_mut(&discord_user_id)
+                .ok_or_else(|| RepositoryError::NotFound {
+                    id: discord_user_id.clone(),
+                })?;
+
+        user_data.display_name = new_display_name;
+        user_data.updated_at = Some(Utc::now());
+
+        Ok(())
+    }
+
+    async fn replace_oauth2_tokens(
+        &self,
+        discord_user_id: String,
+        expected: MemberOAuth2Data,
+        replacement: MemberOAuth2Data,
+    ) -> Result<bool, RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let Some(user_data) = rows.get_mut(&discord_user_id) else {
+            return Ok(false);
+        };
+        if user_data.oauth2.access_token != expected.access_token
+            || user_data.oauth2.refresh_token != expected.refresh_token
+        {
+            return Ok(false);
+        }
+
+        user_data.oauth2.access_token = replacement.access_token;
+        user_data.oauth2.refresh_token = replacement.refresh_token;
+
+        Ok(true)
+    }
+
+    async fn get_member(&self, discord_user_id: &str) -> Result<MemberDataRow, RepositoryError> {
+        let rows = self.rows.lock().await;
+
+        rows.get(discord_user_id)
+            .cloned()
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.to_owned(),
+            })
+    }
+
+    async fn get_all_members(&self) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let rows = self.rows.lock().await;
+
+        Ok(rows.values().cloned().collect())
+    }
+
+    async fn get_members_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let rows = self.rows.lock().await;
+
+        Ok(page(rows.values(), after, limit, |row| {
+            row.discord_user_id.as_str()
+        }))
+    }
+
+    async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        deactivated_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let user_data =
+            rows.get_mut(&discord_user_id)
+                .ok_or_else(|| RepositoryError::NotFound {
+                    id: discord_user_id.clone(),
+                })?;
+
+        user_data.inactive_since = inactive_since;
+        user_data.deactivated_by = deactivated_by;
+
+        Ok(())
+    }
+
+    async fn delete_member(&self, discord_user_id: &str) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+        let mut snapshots = self.snapshots.lock().await;
+
+        rows.remove(discord_user_id)
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.to_owned(),
+            })?;
+        snapshots.remove(discord_user_id);
+
+        Ok(())
+    }
+
+    async fn mark_needs_reauth(
+        &self,
+        discord_user_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let user_data =
+            rows.get_mut(&discord_user_id)
+                .ok_or_else(|| RepositoryError::NotFound {
+                    id: discord_user_id.clone(),
+                })?;
+
+        user_data.needs_reauth_since = Some(since);
+
+        Ok(())
+    }
+
+    async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let user_data =
+            rows.get_mut(&discord_user_id)
+                .ok_or_else(|| RepositoryError::NotFound {
+                    id: discord_user_id.clone(),
+                })?;
+
+        user_data.sync_failure = sync_failure;
+
+        Ok(())
+    }
+
+    async fn save_member_snapshot(&self, snapshot: MemberListRow) -> Result<(), RepositoryError> {
+        let mut snapshots = self.snapshots.lock().await;
+
+        snapshots.insert(snapshot.discord_user_id.clone(), snapshot);
+
+        Ok(())
+    }
+
+    async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<MemberListRow, RepositoryError> {
+        let snapshots = self.snapshots.lock().await;
+
+        snapshots
+            .get(discord_user_id)
+            .cloned()
+            .ok_or_else(|| RepositoryError::NotFound {
+                id: discord_user_id.to_owned(),
+            })
+    }
+
+    async fn get_all_member_snapshots(&self) -> Result<Vec<MemberListRow>, RepositoryError> {
+        let snapshots = self.snapshots.lock().await;
+
+        Ok(snapshots.values().cloned().collect())
+    }
+
+    async fn get_member_snapshots_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberListRow>, RepositoryError> {
+        let snapshots = self.snapshots.lock().await;
+
+        Ok(page(snapshots.values(), after, limit, |snapshot| {
+            snapshot.discord_user_id.as_str()
+        }))
+    }
+
+    async fn append_audit_log(&self, entry: AuditLogEntry) -> Result<(), RepositoryError> {
+        let mut audit_log = self.audit_log.lock().await;
+
+        audit_log.push(entry);
+
+        Ok(())
+    }
+
+    async fn get_audit_log(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        let audit_log = self.audit_log.lock().await;
+
+        Ok(audit_log
+            .iter()
+            .rev()
+            .filter(|entry| discord_user_id.map_or(true, |id| entry.discord_user_id == id))
+            .filter(|entry| !admin_overrides_only || entry.admin_override)
+            .take(usize::try_from(limit).unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_audit_log_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        let audit_log = self.audit_log.lock().await;
+
+        Ok(audit_log
+            .iter()
+            .skip(usize::try_from(offset).unwrap_or(usize::MAX))
+            .take(usize::try_from(limit).unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_member_audit_log_between(
+        &self,
+        discord_user_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        let audit_log = self.audit_log.lock().await;
+
+        Ok(audit_log
+            .iter()
+            .filter(|entry| {
+                entry.discord_user_id == discord_user_id
+                    && from <= entry.recorded_at
+                    && entry.recorded_at < to
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn save_members(&self, members: Vec<MemberDataRow>) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        for member in members {
+            rows.insert(member.discord_user_id.clone(), member);
+        }
+
+        Ok(())
+    }
+
+    async fn get_applied_migrations(&self) -> Result<Vec<AppliedMigration>, RepositoryError> {
+        let migrations = self.migrations.lock().await;
+
+        Ok(migrations.values().cloned().collect())
+    }
+
+    async fn record_migration(&self, migration: AppliedMigration) -> Result<(), RepositoryError> {
+        let mut migrations = self.migrations.lock().await;
+
+        migrations.insert(migration.version, migration);
+
+        Ok(())
+    }
+
+    async fn get_outdated_members_page(
+        &self,
+        schema_version: u32,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let rows = self.rows.lock().await;
+
+        Ok(page(
+            rows.values()
+                .filter(|row| row.schema_version < schema_version),
+            after,
+            limit,
+            |row| row.discord_user_id.as_str(),
+        ))
+    }
+
+    async fn save_migrated_member(
+        &self,
+        member: MemberDataRow,
+        read_version: u32,
+        fields: &[MemberDataField],
+    ) -> Result<bool, RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let Some(user_data) = rows.get_mut(&member.discord_user_id) else {
+            return Ok(false);
+        };
+        if user_data.schema_version != read_version {
+            return Ok(false);
+        }
+
+        for field in fields {
+            match field {
+                MemberDataField::DisplayName => {
+                    user_data.display_name = member.display_name.clone();
+                }
+                MemberDataField::SyncFailure => {
+                    user_data.sync_failure = member.sync_failure.clone();
+                }
+                MemberDataField::NeedsReauthSince => {
+                    user_data.needs_reauth_since = member.needs_reauth_since;
+                }
+                MemberDataField::InactiveSince => user_data.inactive_since = member.inactive_since,
+                MemberDataField::RegisteredAt => user_data.registered_at = member.registered_at,
+                MemberDataField::UpdatedAt => user_data.updated_at = member.updated_at,
+                MemberDataField::LastTokenRefreshAt => {
+                    user_data.last_token_refresh_at = member.last_token_refresh_at;
+                }
+                MemberDataField::DeactivatedBy => {
+                    user_data.deactivated_by = member.deactivated_by.clone();
+                }
+            }
+        }
+        user_data.schema_version = member.schema_version;
+
+        Ok(true)
+    }
+}
+
+/// Sorts the rows by id and returns up to `limit` of them after the id `after`.
+fn page<'a, T, I, F>(rows: I, after: Option<&str>, limit: u32, id: F) -> Vec<T>
+where
+    T: Clone + 'a,
+    I: Iterator<Item = &'a T>,
+    F: Fn(&T) -> &str,
+{
+    let mut rows = rows
+        .filter(|row| after.map_or(true, |after_id| id(*row) > after_id))
+        .collect::<Vec<_>>();
+    rows.sort_by(|left, right| id(*left).cmp(id(*right)));
+
+    rows.into_iter()
+        .take(usize::try_from(limit).unwrap_or(usize::MAX))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::infra::repository::{OAuth2Repository, RepositoryError};
+use crate::model::CsrfTokenData;
+
+#[derive(Clone, Default)]
+pub(crate) struct OAuth2RepositoryImpl {
+    rows: Arc<Mutex<HashMap<String, CsrfTokenData>>>,
+}
+
+impl OAuth2RepositoryImpl {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl OAuth2Repository for OAuth2RepositoryImpl {
+    async fn save_csrf_token(
+        &self,
+        csrf_token: String,
+        pkce_verifier: String,
+        expires_at: DateTime<Utc>,
+        requested_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let data = CsrfTokenData {
+            pkce_verifier,
+            expires_at,
+            requested_by,
+        };
+
+        rows.insert(csrf_token, data);
+
+        Ok(())
+    }
+
+    async fn delete_csrf_token(
+        &self,
+        csrf_token: String,
+    ) -> Result<CsrfTokenData, RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        rows.remove(&csrf_token)
+            .ok_or(RepositoryError::NotFound { id: csrf_token })
+    }
+
+    async fn delete_expired_csrf_tokens(&self, now: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        let mut rows = self.rows.lock().await;
+
+        let mut deleted = 0;
+        rows.retain(|_, data| {
+            let expired = data.expires_at <= now;
+            if expired {
+                deleted += 1;
+            }
+            !expired
+        });
+
+        Ok(deleted)
+    }
+
+    async fn get_all_csrf_tokens(&self) -> Result<Vec<(String, CsrfTokenData)>, RepositoryError> {
+        let rows = self.rows.lock().await;
+
+        Ok(rows
+            .iter()
+            .map(|(csrf_token, data)| (csrf_token.clone(), data.clone()))
+            .collect())
+    }
+}
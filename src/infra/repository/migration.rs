@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context as _;
+use chrono::Utc;
+
+use crate::model::{AppliedMigration, MemberDataField, MemberDataRow, MEMBER_DATA_SCHEMA_VERSION};
+
+use super::MemberDataRepository;
+
+/// Number of outdated members read from the database at once.
+const BATCH_SIZE: u32 = 100;
+
+/// Upgrades a member from the previous version, filling the fields which did not exist before.
+struct Migration {
+    version: u32,
+    name: &'static str,
+    /// Fields which `upgrade` may change, and which are written back with the new version.
+    fields: &'static [MemberDataField],
+    upgrade: fn(&mut MemberDataRow),
+}
+
+/// Every data migration in the order they run. The last one is [`MEMBER_DATA_SCHEMA_VERSION`].
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "add_schema_version",
+        fields: &[],
+        // only marks the members which were saved before the version was recorded.
+        upgrade: |_| {},
+    },
+    Migration {
+        version: 2,
+        name: "add_member_timestamps",
+        fields: &[],
+        // the timestamps of the existing members are not known, and are left unset.
+        upgrade: |_| {},
+    },
+    Migration {
+        version: 3,
+        name: "add_deactivated_by",
+        fields: &[],
+        // inactive members so far all left the guild by themselves.
+        upgrade: |_| {},
+    },
+];
+
+/// Counts the migrations which have not run yet.
+pub(crate) async fn count_pending_migrations<R>(repository: &R) -> anyhow::Result<usize>
+where
+    R: MemberDataRepository + Sync,
+{
+    Ok(get_pending_migrations(repository).await?.len())
+}
+
+/// Runs the migrations which have not run yet, paging through the outdated members.
+///
+/// Members are upgraded from their own version, so that an interrupted run resumes where it stopped.
+/// Each member is written back only if it is still at the version it was read with,
+/// so that concurrent runs do not overwrite each other, nor the changes made meanwhile.
+/// Returns the number of migrations which ran.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn run_migrations<R>(repository: &R) -> anyhow::Result<usize>
+where
+    R: MemberDataRepository + Sync,
+{
+    let pending = get_pending_migrations(repository).await?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let mut migrated_members = HashMap::<u32, u32>::new();
+    let mut saved = 0;
+    let mut after = None::<String>;
+    loop {
+        let outdated = repository
+            .get_outdated_members_page(MEMBER_DATA_SCHEMA_VERSION, after.as_deref(), BATCH_SIZE)
+            .await
+            .context("could not get outdated members data from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+        let Some(last) = outdated.last() else {
+            break;
+        };
+        after = Some(last.discord_user_id.clone());
+
+        for mut member in outdated {
+            let read_version = member.schema_version;
+            let versions = upgrade_member(&mut member);
+            let mut fields = Vec::new();
+            for migration in MIGRATIONS {
+                if versions.contains(&migration.version) {
+                    for field in migration.fields {
+                        if !fields.contains(field) {
+                            fields.push(*field);
+                        }
+                    }
+                }
+            }
+            for version in &versions {
+                *migrated_members.entry(*version).or_default() += 1;
+            }
+
+            // the version is written even when no field changed, so that the member is not read again.
+            let discord_user_id = member.discord_user_id.clone();
+            let replaced = repository
+                .save_migrated_member(member, read_version, &fields)
+                .await
+                .context("could not save migrated member to database")
+                .inspect_err(|err| tracing::error!("{}", err))?;
+            if replaced {
+                saved += 1;
+            } else {
+                tracing::info!("skipped member {} changed meanwhile", discord_user_id);
+            }
+        }
+        tracing::info!("saved {} migrated members so far", saved);
+    }
+
+    for migration in &pending {
+        repository
+            .record_migration(AppliedMigration {
+                version: migration.version,
+                name: migration.name.to_owned(),
+                applied_at: Utc::now(),
+                migrated_members: migrated_members
+                    .get(&migration.version)
+                    .copied()
+                    .unwrap_or_default(),
+            })
+            .await
+            .context("could not record migration to database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+        tracing::info!("applied migration {} {}", migration.version, migration.name);
+    }
+
+    Ok(pending.len())
+}
+
+async fn get_pending_migrations<R>(repository: &R) -> anyhow::Result<Vec<&'static Migration>>
+where
+    R: MemberDataRepository + Sync,
+{
+    let applied = repository
+        .get_applied_migrations()
+        .await
+        .context("could not get applied migrations from database")
+        .inspect_err(|err| tracing::error!("{}", err))?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect::<HashSet<_>>();
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect())
+}
+
+/// Brings the member up to [`MEMBER_DATA_SCHEMA_VERSION`], returning the versions it went through.
+pub(crate) fn upgrade_member(member: &mut MemberDataRow) -> Vec<u32> {
+    let mut versions = Vec::new();
+    for migration in MIGRATIONS {
+        if member.schema_version < migration.version {
+            (migration.upgrade)(member);
+            member.schema_version = migration.version;
+            versions.push(migration.version);
+        }
+    }
+
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::infra::repository::memory::MemberDataRepositoryImpl;
+    use crate::infra::repository::MemberDataRepository;
+    use crate::model::{
+        MemberDataField, MemberDataRow, MemberOAuth2Data, MEMBER_DATA_SCHEMA_VERSION,
+    };
+
+    use super::{count_pending_migrations, run_migrations, upgrade_member, MIGRATIONS};
+
+    fn member(discord_user_id: &str, schema_version: u32) -> MemberDataRow {
+        MemberDataRow {
+            discord_user_id: discord_user_id.to_owned(),
+            oauth2: MemberOAuth2Data {
+                access_token: "access".to_owned(),
+                ..MemberOAuth2Data::default()
+            },
+            schema_version,
+            ..MemberDataRow::default()
+        }
+    }
+
+    #[test]
+    fn migrations_lead_up_to_the_current_version() {
+        let versions = MIGRATIONS
+            .iter()
+            .map(|migration| migration.version)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            versions,
+            (1..=MEMBER_DATA_SCHEMA_VERSION).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn members_are_upgraded_from_their_own_version() {
+        let mut unversioned = member("100", 0);
+        assert_eq!(
+            upgrade_member(&mut unversioned),
+            (1..=MEMBER_DATA_SCHEMA_VERSION).collect::<Vec<_>>()
+        );
+        assert_eq!(unversioned.schema_version, MEMBER_DATA_SCHEMA_VERSION);
+
+        let mut previous = member("200", MEMBER_DATA_SCHEMA_VERSION - 1);
+        assert_eq!(
+            upgrade_member(&mut previous),
+            vec![MEMBER_DATA_SCHEMA_VERSION]
+        );
+
+        let mut current = member("300", MEMBER_DATA_SCHEMA_VERSION);
+        assert!(upgrade_member(&mut current).is_empty());
+    }
+
+    #[tokio::test]
+    async fn migrations_run_once_and_persist_the_version() -> anyhow::Result<()> {
+        let repository = MemberDataRepositoryImpl::new();
+        repository
+            .save_members(vec![
+                member("100", 0),
+                member("200", MEMBER_DATA_SCHEMA_VERSION),
+            ])
+            .await?;
+
+        assert_eq!(
+            count_pending_migrations(&repository).await?,
+            MIGRATIONS.len()
+        );
+        assert_eq!(run_migrations(&repository).await?, MIGRATIONS.len());
+
+        let applied = repository.get_applied_migrations().await?;
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert!(applied
+            .iter()
+            .all(|migration| migration.migrated_members == 1));
+        // written even though none of the migrations so far fills a field.
+        assert_eq!(
+            repository.get_member("100").await?.schema_version,
+            MEMBER_DATA_SCHEMA_VERSION
+        );
+        assert!(repository
+            .get_outdated_members_page(MEMBER_DATA_SCHEMA_VERSION, None, 10)
+            .await?
+            .is_empty());
+
+        assert_eq!(count_pending_migrations(&repository).await?, 0);
+        assert_eq!(run_migrations(&repository).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrated_members_are_written_only_at_the_version_read() -> anyhow::Result<()> {
+        let repository = MemberDataRepositoryImpl::new();
+        repository.save_members(vec![member("100", 1)]).await?;
+
+        let mut migrated = member("100", 1);
+        upgrade_member(&mut migrated);
+        migrated.display_name = Some("migrated".to_owned());
+        migrated.oauth2.access_token = "overwritten".to_owned();
+
+        assert!(
+            !repository
+                .save_migrated_member(migrated.clone(), 0, &[MemberDataField::DisplayName])
+                .await?
+        );
+        assert!(
+            repository
+                .save_migrated_member(migrated, 1, &[MemberDataField::DisplayName])
+                .await?
+        );
+
+        let saved = repository.get_member("100").await?;
+        assert_eq!(saved.schema_version, MEMBER_DATA_SCHEMA_VERSION);
+        assert_eq!(saved.display_name.as_deref(), Some("migrated"));
+        // fields which were not migrated are left as they are.
+        assert_eq!(saved.oauth2.access_token, "access");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outdated_members_are_paged_by_id() -> anyhow::Result<()> {
+        let repository = MemberDataRepositoryImpl::new();
+        repository
+            .save_members(vec![
+                member("100", 0),
+                member("200", MEMBER_DATA_SCHEMA_VERSION),
+                member("300", 1),
+                member("400", 2),
+            ])
+            .await?;
+
+        let first = repository
+            .get_outdated_members_page(MEMBER_DATA_SCHEMA_VERSION, None, 2)
+            .await?;
+        let second = repository
+            .get_outdated_members_page(MEMBER_DATA_SCHEMA_VERSION, Some("300"), 2)
+            .await?;
+
+        let ids = |page: &[MemberDataRow]| {
+            page.iter()
+                .map(|member| member.discord_user_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&first), vec!["100", "300"]);
+        assert_eq!(ids(&second), vec!["400"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+mod members;
+mod oauth2;
+
+pub(crate) use self::oauth2::OAuth2RepositoryImpl;
+pub(crate) use members::MemberDataRepositoryImpl;
+
+use anyhow::Context as _;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+/// Connects a pool of at most `max_connections` to `database_url` and applies pending migrations.
+///
+/// Migrations are guarded by an advisory lock, so replicas starting at the same time are safe.
+pub(crate) async fn connect(database_url: &str, max_connections: u32) -> anyhow::Result<PgPool> {
+    let pool = PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(database_url)
+        .await
+        .context("could not connect to postgres")?;
+
+    sqlx::migrate!("migrations/postgres")
+        .run(&pool)
+        .await
+        .context("could not run postgres migrations")?;
+
+    Ok(pool)
+}
@@ -0,0 +1,767 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgPool};
+
+use crate::infra::repository::{MemberDataRepository, RepositoryError};
+use crate::model::{
+    AppliedMigration, AuditLogEntry, AuditedMemberData, MemberDataField, MemberDataRow,
+    MemberListRow, MemberOAuth2Data, RoleInfo, SyncFailure, MEMBER_DATA_SCHEMA_VERSION,
+};
+
+#[derive(FromRow)]
+struct MemberDataRecord {
+    discord_user_id: String,
+    display_name: Option<String>,
+    access_token: String,
+    refresh_token: String,
+    access_token_expires_at: Option<DateTime<Utc>>,
+    sync_failure_count: i64,
+    sync_last_error: Option<String>,
+    sync_last_failed_at: Option<DateTime<Utc>>,
+    needs_reauth_since: Option<DateTime<Utc>>,
+    inactive_since: Option<DateTime<Utc>>,
+    registered_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    last_token_refresh_at: Option<DateTime<Utc>>,
+    deactivated_by: Option<String>,
+    schema_version: i64,
+}
+
+impl From<MemberDataRecord> for MemberDataRow {
+    fn from(value: MemberDataRecord) -> Self {
+        Self {
+            discord_user_id: value.discord_user_id,
+            display_name: value.display_name,
+            oauth2: MemberOAuth2Data {
+                access_token: value.access_token,
+                refresh_token: value.refresh_token,
+                expires_at: value.access_token_expires_at,
+            },
+            sync_failure: value.sync_last_error.zip(value.sync_last_failed_at).map(
+                |(last_error, last_failed_at)| SyncFailure {
+                    failure_count: u32::try_from(value.sync_failure_count).unwrap_or(u32::MAX),
+                    last_error,
+                    last_failed_at,
+                },
+            ),
+            needs_reauth_since: value.needs_reauth_since,
+            inactive_since: value.inactive_since,
+            registered_at: value.registered_at,
+            updated_at: value.updated_at,
+            last_token_refresh_at: value.last_token_refresh_at,
+            deactivated_by: value.deactivated_by,
+            schema_version: u32::try_from(value.schema_version).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct MemberListRecord {
+    discord_user_id: String,
+    display_name: Option<String>,
+    twitter: Json<Vec<String>>,
+    github: Json<Vec<String>>,
+    role_name: Option<String>,
+    role_color: Option<String>,
+    synced_at: DateTime<Utc>,
+    stale: bool,
+    needs_reauth_since: Option<DateTime<Utc>>,
+    inactive_since: Option<DateTime<Utc>>,
+    registered_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    last_token_refresh_at: Option<DateTime<Utc>>,
+}
+
+impl From<MemberListRecord> for MemberListRow {
+    fn from(value: MemberListRecord) -> Self {
+        Self {
+            discord_user_id: value.discord_user_id,
+            display_name: value.display_name,
+            twitter: value.twitter.0,
+            github: value.github.0,
+            role: value
+                .role_name
+                .zip(value.role_color)
+                .map(|(name, color)| RoleInfo { name, color }),
+            synced_at: value.synced_at,
+            stale: value.stale,
+            needs_reauth_since: value.needs_reauth_since,
+            inactive_since: value.inactive_since,
+            registered_at: value.registered_at,
+            updated_at: value.updated_at,
+            last_token_refresh_at: value.last_token_refresh_at,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct AuditLogRecord {
+    discord_user_id: String,
+    action: String,
+    actor: String,
+    recorded_at: DateTime<Utc>,
+    admin_override: bool,
+    data_before: Option<Json<AuditedMemberData>>,
+    data_after: Option<Json<AuditedMemberData>>,
+}
+
+impl TryFrom<AuditLogRecord> for AuditLogEntry {
+    type Error = RepositoryError;
+
+    fn try_from(value: AuditLogRecord) -> Result<Self, Self::Error> {
+        Ok(Self {
+            discord_user_id: value.discord_user_id,
+            action: value
+                .action
+                .parse()
+                .map_err(|err: String| RepositoryError::InternalError(err.into()))?,
+            actor: value.actor,
+            recorded_at: value.recorded_at,
+            admin_override: value.admin_override,
+            before: value.data_before.map(|data| data.0),
+            after: value.data_after.map(|data| data.0),
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct AppliedMigrationRecord {
+    version: i64,
+    name: String,
+    applied_at: DateTime<Utc>,
+    migrated_members: i64,
+}
+
+impl From<AppliedMigrationRecord> for AppliedMigration {
+    fn from(value: AppliedMigrationRecord) -> Self {
+        Self {
+            version: u32::try_from(value.version).unwrap_or_default(),
+            name: value.name,
+            applied_at: value.applied_at,
+            migrated_members: u32::try_from(value.migrated_members).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MemberDataRepositoryImpl {
+    pool: PgPool,
+}
+
+impl MemberDataRepositoryImpl {
+    pub(crate) fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl MemberDataRepository for MemberDataRepositoryImpl {
+    async fn save_oauth2_token(
+        &self,
+        discord_user_id: String,
+        oauth2: MemberOAuth2Data,
+    ) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO members_data
+                (discord_user_id, access_token, refresh_token, access_token_expires_at,
+                    schema_version, registered_at, updated_at, last_token_refresh_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (discord_user_id) DO UPDATE SET
+                access_token = EXCLUDED.access_token,
+                refresh_token = EXCLUDED.refresh_token,
+                access_token_expires_at = EXCLUDED.access_token_expires_at,
+                needs_reauth_since = NULL,
+                updated_at = EXCLUDED.updated_at,
+                last_token_refresh_at = EXCLUDED.last_token_refresh_at",
+        )
+        .bind(discord_user_id)
+        .bind(oauth2.access_token)
+        .bind(oauth2.refresh_token)
+        .bind(oauth2.expires_at)
+        .bind(i64::from(MEMBER_DATA_SCHEMA_VERSION))
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn save_display_name(
+        &self,
+        discord_user_id: String,
+        new_display_name: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+
+        let exists =
+            sqlx::query("SELECT 1 FROM members_data WHERE discord_user_id = $1 FOR UPDATE")
+                .bind(&discord_user_id)
+                .fetch_optional(&mut transaction)
+                .await?
+                .is_some();
+        if !exists {
+            return Err(RepositoryError::NotFound {
+                id: discord_user_id,
+            });
+        }
+
+        sqlx::query(
+            "UPDATE members_data SET display_name = $1, updated_at = $2 WHERE discord_user_id = $3",
+        )
+        .bind(new_display_name)
+        .bind(Utc::now())
+        .bind(&discord_user_id)
+        .execute(&mut transaction)
+        .await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+        Ok(())
+    }
+
+    async fn replace_oauth2_tokens(
+        &self,
+        discord_user_id: String,
+        expected: MemberOAuth2Data,
+        replacement: MemberOAuth2Data,
+    ) -> Result<bool, RepositoryError> {
+        let updated = sqlx::query(
+            "UPDATE members_data SET access_token = $1, refresh_token = $2
+            WHERE discord_user_id = $3 AND access_token = $4 AND refresh_token = $5",
+        )
+        .bind(replacement.access_token)
+        .bind(replacement.refresh_token)
+        .bind(discord_user_id)
+        .bind(expected.access_token)
+        .bind(expected.refresh_token)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(updated > 0)
+    }
+
+    async fn get_member(&self, discord_user_id: &str) -> Result<MemberDataRow, RepositoryError> {
+        sqlx::query_as::<_, MemberDataRecord>(
+            "SELECT * FROM members_data WHERE discord_user_id = $1",
+        )
+        .bind(discord_user_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(MemberDataRow::from)
+        .ok_or_else(|| RepositoryError::NotFound {
+            id: discord_user_id.to_owned(),
+        })
+    }
+
+    async fn get_all_members(&self) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let member_data = sqlx::query_as::<_, MemberDataRecord>("SELECT * FROM members_data")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(MemberDataRow::from)
+            .collect();
+
+        Ok(member_data)
+    }
+
+    async fn get_members_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let member_data = sqlx::query_as::<_, MemberDataRecord>(
+            "SELECT * FROM members_data
+            WHERE $1::TEXT IS NULL OR discord_user_id > $1
+            ORDER BY discord_user_id
+            LIMIT $2",
+        )
+        .bind(after)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(MemberDataRow::from)
+        .collect();
+
+        Ok(member_data)
+    }
+
+    async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        deactivated_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let updated = sqlx::query(
+            "UPDATE members_data SET inactive_since = $1, deactivated_by = $2
+            WHERE discord_user_id = $3",
+        )
+        .bind(inactive_since)
+        .bind(deactivated_by)
+        .bind(&discord_user_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(RepositoryError::NotFound {
+                id: discord_user_id,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn delete_member(&self, discord_user_id: &str) -> Result<(), RepositoryError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+
+        let deleted = sqlx::query("DELETE FROM members_data WHERE discord_user_id = $1")
+            .bind(discord_user_id)
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(RepositoryError::NotFound {
+                id: discord_user_id.to_owned(),
+            });
+        }
+
+        sqlx::query("DELETE FROM members_list WHERE discord_user_id = $1")
+            .bind(discord_user_id)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+        Ok(())
+    }
+
+    async fn mark_needs_reauth(
+        &self,
+        discord_user_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        let updated = sqlx::query(
+            "UPDATE members_data SET needs_reauth_since = $1 WHERE discord_user_id = $2",
+        )
+        .bind(since)
+        .bind(&discord_user_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(RepositoryError::NotFound {
+                id: discord_user_id,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> Result<(), RepositoryError> {
+        let (failure_count, last_error, last_failed_at) = match sync_failure {
+            Some(sync_failure) => (
+                sync_failure.failure_count,
+                Some(sync_failure.last_error),
+                Some(sync_failure.last_failed_at),
+            ),
+            None => (0, None, None),
+        };
+
+        let updated = sqlx::query(
+            "UPDATE members_data SET
+                sync_failure_count = $1,
+                sync_last_error = $2,
+                sync_last_failed_at = $3
+            WHERE discord_user_id = $4",
+        )
+        .bind(i64::from(failure_count))
+        .bind(last_error)
+        .bind(last_failed_at)
+        .bind(&discord_user_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(RepositoryError::NotFound {
+                id: discord_user_id,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn save_member_snapshot(&self, snapshot: MemberListRow) -> Result<(), RepositoryError> {
+        let (role_name, role_color) = snapshot.role.map(|role| (role.name, role.color)).unzip();
+
+        sqlx::query(
+            "INSERT INTO members_list
+                (discord_user_id, display_name, twitter, github, role_name, role_color, synced_at,
+                    stale, needs_reauth_since, inactive_since, registered_at, updated_at,
+                    last_token_refresh_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            ON CONFLICT (discord_user_id) DO UPDATE SET
+                display_name = EXCLUDED.display_name,
+                twitter = EXCLUDED.twitter,
+                github = EXCLUDED.github,
+                role_name = EXCLUDED.role_name,
+                role_color = EXCLUDED.role_color,
+                synced_at = EXCLUDED.synced_at,
+                stale = EXCLUDED.stale,
+                needs_reauth_since = EXCLUDED.needs_reauth_since,
+                inactive_since = EXCLUDED.inactive_since,
+                registered_at = EXCLUDED.registered_at,
+                updated_at = EXCLUDED.updated_at,
+                last_token_refresh_at = EXCLUDED.last_token_refresh_at",
+        )
+        .bind(snapshot.discord_user_id)
+        .bind(snapshot.display_name)
+        .bind(Json(snapshot.twitter))
+        .bind(Json(snapshot.github))
+        .bind(role_name)
+        .bind(role_color)
+        .bind(snapshot.synced_at)
+        .bind(snapshot.stale)
+        .bind(snapshot.needs_reauth_since)
+        .bind(snapshot.inactive_since)
+        .bind(snapshot.registered_at)
+        .bind(snapshot.updated_at)
+        .bind(snapshot.last_token_refresh_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<MemberListRow, RepositoryError> {
+        sqlx::query_as::<_, MemberListRecord>(
+            "SELECT * FROM members_list WHERE discord_user_id = $1",
+        )
+        .bind(discord_user_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(MemberListRow::from)
+        .ok_or_else(|| RepositoryError::NotFound {
+            id: discord_user_id.to_owned(),
+        })
+    }
+
+    async fn get_all_member_snapshots(&self) -> Result<Vec<MemberListRow>, RepositoryError> {
+        let snapshots = sqlx::query_as::<_, MemberListRecord>("SELECT * FROM members_list")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(MemberListRow::from)
+            .collect();
+
+        Ok(snapshots)
+    }
+
+    async fn get_member_snapshots_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberListRow>, RepositoryError> {
+        let snapshots = sqlx::query_as::<_, MemberListRecord>(
+            "SELECT * FROM members_list
+            WHERE $1::TEXT IS NULL OR discord_user_id > $1
+            ORDER BY discord_user_id
+            LIMIT $2",
+        )
+        .bind(after)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(MemberListRow::from)
+        .collect();
+
+        Ok(snapshots)
+    }
+
+    async fn append_audit_log(&self, entry: AuditLogEntry) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "INSERT INTO members_audit_log
+                (discord_user_id, action, actor, recorded_at, admin_override,
+                    data_before, data_after)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(entry.discord_user_id)
+        .bind(entry.action.as_str())
+        .bind(entry.actor)
+        .bind(entry.recorded_at)
+        .bind(entry.admin_override)
+        .bind(entry.before.map(Json))
+        .bind(entry.after.map(Json))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_audit_log(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        sqlx::query_as::<_, AuditLogRecord>(
+            "SELECT * FROM members_audit_log
+            WHERE ($1::TEXT IS NULL OR discord_user_id = $1) AND (NOT $2 OR admin_override)
+            ORDER BY id DESC
+            LIMIT $3",
+        )
+        .bind(discord_user_id)
+        .bind(admin_overrides_only)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(AuditLogEntry::try_from)
+        .collect()
+    }
+
+    async fn get_audit_log_page(
+        &self,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        sqlx::query_as::<_, AuditLogRecord>(
+            "SELECT * FROM members_audit_log ORDER BY id LIMIT $1 OFFSET $2",
+        )
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(AuditLogEntry::try_from)
+        .collect()
+    }
+
+    async fn get_member_audit_log_between(
+        &self,
+        discord_user_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AuditLogEntry>, RepositoryError> {
+        sqlx::query_as::<_, AuditLogRecord>(
+            "SELECT * FROM members_audit_log
+            WHERE discord_user_id = $1 AND recorded_at >= $2 AND recorded_at < $3
+            ORDER BY id",
+        )
+        .bind(discord_user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(AuditLogEntry::try_from)
+        .collect()
+    }
+
+    async fn save_members(&self, members: Vec<MemberDataRow>) -> Result<(), RepositoryError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+
+        for member in members {
+            let (failure_count, last_error, last_failed_at) = match member.sync_failure {
+                Some(sync_failure) => (
+                    sync_failure.failure_count,
+                    Some(sync_failure.last_error),
+                    Some(sync_failure.last_failed_at),
+                ),
+                None => (0, None, None),
+            };
+
+            sqlx::query(
+                "INSERT INTO members_data
+                    (discord_user_id, display_name, access_token, refresh_token,
+                        access_token_expires_at, sync_failure_count, sync_last_error,
+                        sync_last_failed_at, needs_reauth_since, inactive_since, registered_at,
+                        updated_at, last_token_refresh_at, deactivated_by, schema_version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                ON CONFLICT (discord_user_id) DO UPDATE SET
+                    display_name = EXCLUDED.display_name,
+                    access_token = EXCLUDED.access_token,
+                    refresh_token = EXCLUDED.refresh_token,
+                    access_token_expires_at = EXCLUDED.access_token_expires_at,
+                    sync_failure_count = EXCLUDED.sync_failure_count,
+                    sync_last_error = EXCLUDED.sync_last_error,
+                    sync_last_failed_at = EXCLUDED.sync_last_failed_at,
+                    needs_reauth_since = EXCLUDED.needs_reauth_since,
+                    inactive_since = EXCLUDED.inactive_since,
+                    registered_at = EXCLUDED.registered_at,
+                    updated_at = EXCLUDED.updated_at,
+                    last_token_refresh_at = EXCLUDED.last_token_refresh_at,
+                    deactivated_by = EXCLUDED.deactivated_by,
+                    schema_version = EXCLUDED.schema_version",
+            )
+            .bind(member.discord_user_id)
+            .bind(member.display_name)
+            .bind(member.oauth2.access_token)
+            .bind(member.oauth2.refresh_token)
+            .bind(member.oauth2.expires_at)
+            .bind(i64::from(failure_count))
+            .bind(last_error)
+            .bind(last_failed_at)
+            .bind(member.needs_reauth_since)
+            .bind(member.inactive_since)
+            .bind(member.registered_at)
+            .bind(member.updated_at)
+            .bind(member.last_token_refresh_at)
+            .bind(member.deactivated_by)
+            .bind(i64::from(member.schema_version))
+            .execute(&mut transaction)
+            .await?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+        Ok(())
+    }
+
+    async fn get_applied_migrations(&self) -> Result<Vec<AppliedMigration>, RepositoryError> {
+        let migrations =
+            sqlx::query_as::<_, AppliedMigrationRecord>("SELECT * FROM members_data_migrations")
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(AppliedMigration::from)
+                .collect();
+
+        Ok(migrations)
+    }
+
+    async fn record_migration(&self, migration: AppliedMigration) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "INSERT INTO members_data_migrations (version, name, applied_at, migrated_members)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (version) DO UPDATE SET
+                name = EXCLUDED.name,
+                applied_at = EXCLUDED.applied_at,
+                migrated_members = EXCLUDED.migrated_members",
+        )
+        .bind(i64::from(migration.version))
+        .bind(migration.name)
+        .bind(migration.applied_at)
+        .bind(i64::from(migration.migrated_members))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_outdated_members_page(
+        &self,
+        schema_version: u32,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<MemberDataRow>, RepositoryError> {
+        let member_data = sqlx::query_as::<_, MemberDataRecord>(
+            "SELECT * FROM members_data
+            WHERE schema_version < $1 AND ($2::TEXT IS NULL OR discord_user_id > $2)
+            ORDER BY discord_user_id
+            LIMIT $3",
+        )
+        .bind(i64::from(schema_version))
+        .bind(after)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(MemberDataRow::from)
+        .collect();
+
+        Ok(member_data)
+    }
+
+    async fn save_migrated_member(
+        &self,
+        member: MemberDataRow,
+        read_version: u32,
+        fields: &[MemberDataField],
+    ) -> Result<bool, RepositoryError> {
+        let migrated = |field| fields.contains(&field);
+        let (failure_count, last_error, last_failed_at) = match member.sync_failure {
+            Some(sync_failure) => (
+                sync_failure.failure_count,
+                Some(sync_failure.last_error),
+                Some(sync_failure.last_failed_at),
+            ),
+            None => (0, None, None),
+        };
+
+        // columns which were not migrated are assigned their own value.
+        let updated = sqlx::query(
+            "UPDATE members_data SET
+                display_name = CASE WHEN $1 THEN $2 ELSE display_name END,
+                sync_failure_count = CASE WHEN $3 THEN $4 ELSE sync_failure_count END,
+                sync_last_error = CASE WHEN $5 THEN $6 ELSE sync_last_error END,
+                sync_last_failed_at = CASE WHEN $7 THEN $8 ELSE sync_last_failed_at END,
+                needs_reauth_since = CASE WHEN $9 THEN $10 ELSE needs_reauth_since END,
+                inactive_since = CASE WHEN $11 THEN $12 ELSE inactive_since END,
+                registered_at = CASE WHEN $13 THEN $14 ELSE registered_at END,
+                updated_at = CASE WHEN $15 THEN $16 ELSE updated_at END,
+                last_token_refresh_at = CASE WHEN $17 THEN $18 ELSE last_token_refresh_at END,
+                deactivated_by = CASE WHEN $19 THEN $20 ELSE deactivated_by END,
+                schema_version = $21
+            WHERE discord_user_id = $22 AND schema_version = $23",
+        )
+        .bind(migrated(MemberDataField::DisplayName))
+        .bind(member.display_name)
+        .bind(migrated(MemberDataField::SyncFailure))
+        .bind(i64::from(failure_count))
+        .bind(migrated(MemberDataField::SyncFailure))
+        .bind(last_error)
+        .bind(migrated(MemberDataField::SyncFailure))
+        .bind(last_failed_at)
+        .bind(migrated(MemberDataField::NeedsReauthSince))
+        .bind(member.needs_reauth_since)
+        .bind(migrated(MemberDataField::InactiveSince))
+        .bind(member.inactive_since)
+        .bind(migrated(MemberDataField::RegisteredAt))
+        .bind(member.registered_at)
+        .bind(migrated(MemberDataField::UpdatedAt))
+        .bind(member.updated_at)
+        .bind(migrated(MemberDataField::LastTokenRefreshAt))
+        .bind(member.last_token_refresh_at)
+        .bind(migrated(MemberDataField::DeactivatedBy))
+        .bind(member.deactivated_by)
+        .bind(i64::from(member.schema_version))
+        .bind(member.discord_user_id)
+        .bind(i64::from(read_version))
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(updated > 0)
+    }
+}
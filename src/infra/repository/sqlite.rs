@@ -0,0 +1,29 @@
+mod members;
+mod oauth2;
+
+pub(crate) use self::oauth2::OAuth2RepositoryImpl;
+pub(crate) use members::MemberDataRepositoryImpl;
+
+use std::str::FromStr as _;
+
+use anyhow::Context as _;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+
+/// Opens the database at `database_url`, creating it if needed, and applies pending migrations.
+pub(crate) async fn connect(database_url: &str) -> anyhow::Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(database_url)
+        .context("could not parse sqlite database url")?
+        .create_if_missing(true);
+
+    let pool = SqlitePoolOptions::new()
+        .connect_with(options)
+        .await
+        .context("could not open sqlite database")?;
+
+    sqlx::migrate!("migrations/sqlite")
+        .run(&pool)
+        .await
+        .context("could not run sqlite migrations")?;
+
+    Ok(pool)
+}
@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqlitePool};
+
+use crate::infra::repository::{OAuth2Repository, RepositoryError};
+use crate::model::CsrfTokenData;
+
+#[derive(FromRow)]
+struct CsrfTokenRecord {
+    pkce_verifier: String,
+    expires_at: DateTime<Utc>,
+    requested_by: Option<String>,
+}
+
+impl From<CsrfTokenRecord> for CsrfTokenData {
+    fn from(value: CsrfTokenRecord) -> Self {
+        Self {
+            pkce_verifier: value.pkce_verifier,
+            expires_at: value.expires_at,
+            requested_by: value.requested_by,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct StoredCsrfTokenRecord {
+    csrf_token: String,
+    #[sqlx(flatten)]
+    record: CsrfTokenRecord,
+}
+
+#[derive(Clone)]
+pub(crate) struct OAuth2RepositoryImpl {
+    pool: SqlitePool,
+}
+
+impl OAuth2RepositoryImpl {
+    pub(crate) fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OAuth2Repository for OAuth2RepositoryImpl {
+    async fn save_csrf_token(
+        &self,
+        csrf_token: String,
+        pkce_verifier: String,
+        expires_at: DateTime<Utc>,
+        requested_by: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO oauth2_data
+                (csrf_token, pkce_verifier, expires_at, requested_by)
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(csrf_token)
+        .bind(pkce_verifier)
+        .bind(expires_at)
+        .bind(requested_by)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_csrf_token(
+        &self,
+        csrf_token: String,
+    ) -> Result<CsrfTokenData, RepositoryError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+
+        let data = sqlx::query_as::<_, CsrfTokenRecord>(
+            "SELECT pkce_verifier, expires_at, requested_by FROM oauth2_data WHERE csrf_token = ?",
+        )
+        .bind(&csrf_token)
+        .fetch_optional(&mut transaction)
+        .await?
+        .ok_or_else(|| RepositoryError::NotFound {
+            id: csrf_token.clone(),
+        })?;
+
+        sqlx::query("DELETE FROM oauth2_data WHERE csrf_token = ?")
+            .bind(&csrf_token)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|err| RepositoryError::TransactionError(Box::new(err)))?;
+
+        Ok(data.into())
+    }
+
+    async fn delete_expired_csrf_tokens(&self, now: DateTime<Utc>) -> Result<u64, RepositoryError> {
+        let result = sqlx::query("DELETE FROM oauth2_data WHERE expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_all_csrf_tokens(&self) -> Result<Vec<(String, CsrfTokenData)>, RepositoryError> {
+        let rows = sqlx::query_as::<_, StoredCsrfTokenRecord>(
+            "SELECT csrf_token, pkce_verifier, expires_at, requested_by FROM oauth2_data",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.csrf_token, row.record.into()))
+            .collect())
+    }
+}
@@ -52,31 +52,29 @@
 )]
 #![feature(result_option_inspect)]
 
-use std::sync::Arc;
-
+use clap::Parser as _;
 use dotenvy::dotenv;
 
-use crate::controller::discord::start_discord_bot;
-use crate::controller::http::start_http_server;
-use crate::usecase::firebase::get_firebase_usecases;
+use crate::config::Config;
+use crate::controller::cli::Cli;
 
+pub(crate) mod config;
 pub(crate) mod controller;
 pub(crate) mod infra;
 pub(crate) mod model;
 pub(crate) mod service;
 pub(crate) mod usecase;
-pub(crate) mod util;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    dotenv().ok();
-    tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
 
-    let usecases = get_firebase_usecases().await?;
+    dotenv().ok();
+    // stdout is left to the output of the commands, such as `export`.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
 
-    tokio::try_join!(
-        start_http_server(Arc::clone(&usecases)),
-        start_discord_bot(usecases),
-    )
-    .map(|_| ())
+    let config = Config::load(cli.config_scope())?;
+    cli.run(config).await
 }
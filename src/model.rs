@@ -1,37 +1,256 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Version of the member data written by this build, the one of the latest data migration.
+pub(crate) const MEMBER_DATA_SCHEMA_VERSION: u32 = 3;
+
+/// New fields need `#[serde(default)]`, so that older rows still deserialize until they are migrated.
+/// Tests build rows from `MemberDataRow::default()`, overriding the fields they are about.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(Default))]
 pub(crate) struct MemberDataRow {
     pub discord_user_id: String,
     pub display_name: Option<String>,
     pub oauth2: MemberOAuth2Data,
+    /// Set while fetching the member from discord keeps failing.
+    #[serde(default)]
+    pub sync_failure: Option<SyncFailure>,
+    /// When discord rejected the refresh-token. Cleared once the member links again.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub needs_reauth_since: Option<DateTime<Utc>>,
+    /// When the member left the guild. Cleared once the member joins again.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub inactive_since: Option<DateTime<Utc>>,
+    /// When the member first linked through OAuth2. Unknown for members registered before it was recorded.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub registered_at: Option<DateTime<Utc>>,
+    /// When the token or the display name was last saved.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// When a token was last issued to the member, by linking or by a refresh.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub last_token_refresh_at: Option<DateTime<Utc>>,
+    /// Moderator who marked the member inactive. Such members stay inactive even while they
+    /// are in the guild, until a moderator marks them active again.
+    #[serde(default)]
+    pub deactivated_by: Option<String>,
+    /// Rows written before it was recorded are version 0.
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Fields of the member data which a data migration may fill.
+/// The tokens are left out, since they are encrypted by another layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MemberDataField {
+    DisplayName,
+    SyncFailure,
+    NeedsReauthSince,
+    InactiveSince,
+    RegisteredAt,
+    UpdatedAt,
+    LastTokenRefreshAt,
+    DeactivatedBy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(Default))]
 pub(crate) struct MemberOAuth2Data {
     pub access_token: String,
     pub refresh_token: String,
+    /// `None` for tokens saved before the expiry was recorded.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SyncFailure {
+    /// Number of consecutive synchronizations which failed.
+    pub failure_count: u32,
+    pub last_error: String,
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    pub last_failed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct CsrfTokenData {
     pub pkce_verifier: String,
     #[serde(with = "firestore::serialize_as_timestamp")]
     pub expires_at: DateTime<Utc>,
+    /// Discord user id of the member who asked for the auth-url, who alone may complete it.
+    #[serde(default)]
+    pub requested_by: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct MemberListRow {
     pub discord_user_id: String,
     pub display_name: Option<String>,
     pub twitter: Vec<String>,
     pub github: Vec<String>,
     pub role: Option<RoleInfo>,
+    /// When the connections and the role were fetched from discord.
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    pub synced_at: DateTime<Utc>,
+    /// Whether the latest synchronization failed, so that the values may be outdated.
+    #[serde(default)]
+    pub stale: bool,
+    /// When the member revoked the authorization, which has to be given again through OAuth2.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub needs_reauth_since: Option<DateTime<Utc>>,
+    /// When the member left the guild.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub inactive_since: Option<DateTime<Utc>>,
+    /// When the member first linked through OAuth2, `None` if it is unknown.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub registered_at: Option<DateTime<Utc>>,
+    /// When the token or the display name was last saved.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// When a token was last issued to the member. Old values mean the entry may be outdated.
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub last_token_refresh_at: Option<DateTime<Utc>>,
+}
+
+/// Registered member without any snapshot, which is not served until it is synchronized.
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct UnsyncedMember {
+    pub discord_user_id: String,
+    pub reason: UnsyncedReason,
+    /// Error of the latest synchronization, if it failed.
+    pub last_error: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UnsyncedReason {
+    /// Registered after the latest synchronization.
+    Pending,
+    /// Every synchronization so far has failed.
+    SyncFailed,
+    /// Revoked the authorization before it was ever synchronized.
+    NeedsReauth,
+    /// Left the guild before it was ever synchronized.
+    Inactive,
+}
+
+impl From<&MemberDataRow> for UnsyncedMember {
+    fn from(value: &MemberDataRow) -> Self {
+        let reason = if value.needs_reauth_since.is_some() {
+            UnsyncedReason::NeedsReauth
+        } else if value.inactive_since.is_some() {
+            UnsyncedReason::Inactive
+        } else if value.sync_failure.is_some() {
+            UnsyncedReason::SyncFailed
+        } else {
+            UnsyncedReason::Pending
+        };
+
+        Self {
+            discord_user_id: value.discord_user_id.clone(),
+            reason,
+            last_error: value
+                .sync_failure
+                .as_ref()
+                .map(|sync_failure| sync_failure.last_error.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct RoleInfo {
     pub name: String,
     pub color: String,
 }
+
+/// Entry of the append-only audit log. Tokens are never recorded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct AuditLogEntry {
+    pub discord_user_id: String,
+    pub action: AuditAction,
+    /// `system`, `member:{discord_user_id}` or `moderator:{discord_user_id}`.
+    pub actor: String,
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    pub recorded_at: DateTime<Utc>,
+    /// Set when a moderator changed the member through an admin command, rather than the
+    /// member itself or the system.
+    #[serde(default)]
+    pub admin_override: bool,
+    /// `None` when the member was not registered yet.
+    pub before: Option<AuditedMemberData>,
+    /// `None` when the member was deleted.
+    pub after: Option<AuditedMemberData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AuditAction {
+    Registered,
+    TokenRefreshed,
+    DisplayNameChanged,
+    MembershipChanged,
+    Deleted,
+}
+
+impl AuditAction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Registered => "registered",
+            Self::TokenRefreshed => "token_refreshed",
+            Self::DisplayNameChanged => "display_name_changed",
+            Self::MembershipChanged => "membership_changed",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "registered" => Ok(Self::Registered),
+            "token_refreshed" => Ok(Self::TokenRefreshed),
+            "display_name_changed" => Ok(Self::DisplayNameChanged),
+            "membership_changed" => Ok(Self::MembershipChanged),
+            "deleted" => Ok(Self::Deleted),
+            _ => Err(format!("unknown audit action '{s}'")),
+        }
+    }
+}
+
+/// Member data as recorded in the audit log, without the tokens.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuditedMemberData {
+    pub display_name: Option<String>,
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub access_token_expires_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub needs_reauth_since: Option<DateTime<Utc>>,
+    #[serde(default, with = "firestore::serialize_as_optional_timestamp")]
+    pub inactive_since: Option<DateTime<Utc>>,
+}
+
+impl From<&MemberDataRow> for AuditedMemberData {
+    fn from(value: &MemberDataRow) -> Self {
+        Self {
+            display_name: value.display_name.clone(),
+            access_token_expires_at: value.oauth2.expires_at,
+            needs_reauth_since: value.needs_reauth_since,
+            inactive_since: value.inactive_since,
+        }
+    }
+}
+
+/// Record of a data migration which has upgraded the stored member data.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    pub applied_at: DateTime<Utc>,
+    /// Number of members which were upgraded by it.
+    pub migrated_members: u32,
+}
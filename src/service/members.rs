@@ -1,19 +1,53 @@
-use anyhow::Context;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Context};
+use chrono::{DateTime, Utc};
 use futures_util::{stream, StreamExt as _};
-use serenity::http::Http;
+use serenity::http::{Http, HttpError};
 use serenity::model::guild::Role;
+use tokio::sync::Mutex;
+use tokio::time::{self, Interval, MissedTickBehavior};
 
 use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
-use crate::model::{MemberDataRow, MemberListRow, RoleInfo};
+use crate::model::{MemberDataRow, MemberListRow, RoleInfo, SyncFailure, UnsyncedMember};
+use crate::usecase::audit::Actor;
 use crate::usecase::members::MembersUseCase;
-use crate::usecase::oauth2::OAuth2UseCase;
+use crate::usecase::oauth2::{OAuth2Error, OAuth2UseCase};
 
 #[derive(Clone)]
 pub(crate) struct MembersService<MR: Clone, OR: Clone> {
     members_usecase: MembersUseCase<MR>,
     oauth2_usecase: OAuth2UseCase<MR, OR>,
     guild_id: u64,
-    bot_token: String,
+    /// Shared with the other usecases, see [`crate::usecase::UseCaseContainer::new`].
+    bot_http: Arc<Http>,
+    sync_concurrency: usize,
+    /// Paces the background jobs, so that they leave the rate limit of the bot to the commands.
+    sync_pacer: RequestPacer,
+}
+
+/// Lets requests start at most once per period, however many of them run concurrently.
+#[derive(Clone)]
+struct RequestPacer {
+    interval: Arc<Mutex<Interval>>,
+}
+
+impl RequestPacer {
+    fn new(period: Duration) -> Self {
+        let mut interval = time::interval(period);
+        // no burst to catch up after the jobs have been idle.
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Self {
+            interval: Arc::new(Mutex::new(interval)),
+        }
+    }
+
+    async fn wait(&self) {
+        self.interval.lock().await.tick().await;
+    }
 }
 
 impl<MR, OR> MembersService<MR, OR>
@@ -25,55 +59,330 @@ where
         members_usecase: MembersUseCase<MR>,
         oauth2_usecase: OAuth2UseCase<MR, OR>,
         guild_id: u64,
-        bot_token: String,
-    ) -> Self {
-        Self {
+        bot_http: Arc<Http>,
+        sync_concurrency: usize,
+        sync_request_interval: Duration,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            sync_concurrency > 0,
+            "member sync concurrency must be greater than 0"
+        );
+
+        Ok(Self {
             members_usecase,
             oauth2_usecase,
             guild_id,
-            bot_token,
+            bot_http,
+            sync_concurrency,
+            sync_pacer: RequestPacer::new(sync_request_interval),
+        })
+    }
+
+    pub(crate) fn guild_id(&self) -> u64 {
+        self.guild_id
+    }
+
+    /// Returns the members as of their last synchronization.
+    ///
+    /// Members whose latest synchronization failed are flagged as `stale`, and members which
+    /// were never synchronized successfully are left to [`Self::get_unsynced_members`].
+    /// Members who left the guild are left out unless `include_inactive` is set.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn get_all_members(
+        &self,
+        include_inactive: bool,
+    ) -> anyhow::Result<Vec<MemberListRow>> {
+        let snapshots = self.members_usecase.get_all_member_snapshots().await?;
+
+        Ok(snapshots
+            .into_iter()
+            .filter(|snapshot| include_inactive || snapshot.inactive_since.is_none())
+            .collect())
+    }
+
+    /// Returns the registered members which were never synchronized successfully, with the reason.
+    /// Members who left the guild are left out unless `include_inactive` is set.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn get_unsynced_members(
+        &self,
+        include_inactive: bool,
+    ) -> anyhow::Result<Vec<UnsyncedMember>> {
+        let snapshots = self.members_usecase.get_all_member_snapshots().await?;
+        let synced = snapshots
+            .iter()
+            .map(|snapshot| snapshot.discord_user_id.as_str())
+            .collect::<HashSet<_>>();
+
+        Ok(self
+            .members_usecase
+            .get_all_members()
+            .await?
+            .iter()
+            .filter(|member| !synced.contains(member.discord_user_id.as_str()))
+            .filter(|member| include_inactive || member.inactive_since.is_none())
+            .map(UnsyncedMember::from)
+            .collect())
+    }
+
+    /// Records that the member left the guild (`Some`) or joined it again (`None`).
+    /// Returns `false` when the user is not registered, or when the change is made by the
+    /// system to a member a moderator deactivated, which only a moderator can change.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn update_membership(
+        &self,
+        member_id: &str,
+        inactive_since: Option<DateTime<Utc>>,
+        actor: &Actor,
+    ) -> anyhow::Result<bool> {
+        if matches!(actor, Actor::System) {
+            let Some(member) = self.members_usecase.get_member(member_id).await? else {
+                return Ok(false);
+            };
+            if member.deactivated_by.is_some() {
+                return Ok(false);
+            }
+        }
+
+        if !self
+            .members_usecase
+            .save_inactive_since(member_id.to_owned(), inactive_since, actor)
+            .await?
+        {
+            return Ok(false);
+        }
+        self.members_usecase
+            .save_member_snapshot_inactive_since(member_id, inactive_since)
+            .await?;
+
+        // the roles of members who came back are most likely different from the snapshot.
+        if inactive_since.is_none() {
+            if let Err(err) = self.sync_member(member_id).await {
+                tracing::warn!(
+                    "could not sync member who joined again, it is synced on next tick: {}",
+                    err
+                );
+            }
         }
+
+        Ok(true)
     }
 
+    /// Catches up with members who left or joined the guild while the bot was offline.
     #[tracing::instrument(skip(self))]
-    pub(crate) async fn get_all_members(&self) -> anyhow::Result<Vec<MemberListRow>> {
+    pub(crate) async fn reconcile_guild_membership(&self) -> anyhow::Result<()> {
         let members = self.members_usecase.get_all_members().await?;
 
-        stream::iter(members.iter())
-            .then(|m| async move { self._get_member(m).await })
-            .collect::<Vec<anyhow::Result<MemberListRow>>>()
+        let mut deactivated = 0;
+        let mut reactivated = 0;
+        for member in &members {
+            self.sync_pacer.wait().await;
+            let in_guild = match self
+                .bot_http
+                .get_member(self.guild_id, member.discord_user_id.parse()?)
+                .await
+            {
+                Ok(_) => true,
+                Err(err) if is_unknown_member(&err) => false,
+                Err(err) => {
+                    tracing::warn!(
+                        "could not fetch guild member from discord: userId: {}, error: {}",
+                        member.discord_user_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            match reconcile_transition(in_guild, member) {
+                Some(MembershipTransition::Deactivate) => {
+                    self.update_membership(
+                        &member.discord_user_id,
+                        Some(Utc::now()),
+                        &Actor::System,
+                    )
+                    .await?;
+                    deactivated += 1;
+                }
+                Some(MembershipTransition::Reactivate) => {
+                    self.update_membership(&member.discord_user_id, None, &Actor::System)
+                        .await?;
+                    reactivated += 1;
+                }
+                None => {}
+            }
+        }
+        tracing::info!(
+            "reconciled guild membership: deactivated: {}, reactivated: {}",
+            deactivated,
+            reactivated
+        );
+
+        Ok(())
+    }
+
+    /// Returns the member as of its last synchronization, `None` if it was never synchronized.
+    ///
+    /// Never calls discord, so that reads of unknown ids cannot use up the rate limit of the bot.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn get_member(
+        &self,
+        member_id: &str,
+    ) -> anyhow::Result<Option<MemberListRow>> {
+        self.members_usecase.get_member_snapshot(member_id).await
+    }
+
+    /// Fetches every member from discord and saves them as snapshots.
+    /// Returns the number of members which were synchronized.
+    ///
+    /// Up to `sync_concurrency` members are fetched at once, and a new one is started at most
+    /// once per `sync_request_interval`. Requests which still hit discord's rate limits are
+    /// retried by serenity after the `Retry-After` discord answered.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn sync_all_members(&self) -> anyhow::Result<usize> {
+        let (members, skipped): (Vec<_>, Vec<_>) = self
+            .members_usecase
+            .get_all_members()
+            .await?
+            .into_iter()
+            .partition(|m| m.needs_reauth_since.is_none() && m.inactive_since.is_none());
+        if !skipped.is_empty() {
+            tracing::info!(
+                "skipped {} members who need to authorize again or left the guild",
+                skipped.len()
+            );
+        }
+
+        let fetched_roles = self.get_guild_roles().await;
+        let guild_roles = fetched_roles.as_deref();
+
+        // collected first, as mapping the stream keeps the spawned job from being provably `Send`.
+        let syncs = members
+            .iter()
+            .map(|m| async move {
+                self.sync_pacer.wait().await;
+                self._sync_member(m, guild_roles)
+                    .await
+                    .inspect_err(|err| {
+                        tracing::warn!(
+                            "could not sync member: userId: {}, error: {:#}",
+                            m.discord_user_id,
+                            err
+                        )
+                    })
+                    .is_ok()
+            })
+            .collect::<Vec<_>>();
+        let synced = stream::iter(syncs)
+            .buffer_unordered(self.sync_concurrency)
+            .collect::<Vec<_>>()
             .await
             .into_iter()
-            .collect::<anyhow::Result<Vec<_>>>()
+            .filter(|synced| *synced)
+            .count();
+        tracing::info!("synced {}/{} members", synced, members.len());
+
+        Ok(synced)
     }
 
+    /// Fetches the member from discord and saves it as the snapshot.
     #[tracing::instrument(skip(self))]
-    pub(crate) async fn get_member(
+    pub(crate) async fn sync_member(
         &self,
         member_id: &str,
     ) -> anyhow::Result<Option<MemberListRow>> {
         let member_data = self.members_usecase.get_member(member_id).await?;
 
         match member_data {
-            Some(member_data) => self._get_member(&member_data).await.map(Some),
+            Some(member_data) => {
+                let guild_roles = self.get_guild_roles().await;
+                self._sync_member(&member_data, guild_roles.as_deref())
+                    .await
+                    .map(Some)
+            }
             None => Ok(None),
         }
     }
 
-    async fn _get_member(&self, member_data: &MemberDataRow) -> anyhow::Result<MemberListRow> {
+    async fn _sync_member(
+        &self,
+        member_data: &MemberDataRow,
+        guild_roles: Option<&[Role]>,
+    ) -> anyhow::Result<MemberListRow> {
+        let member = match self._get_member(member_data, guild_roles).await {
+            Ok(member) => member,
+            Err(err) => {
+                self.record_sync_failure(member_data, &err).await;
+                return Err(err);
+            }
+        };
+        self.members_usecase
+            .save_member_snapshot(member.clone())
+            .await?;
+
+        if member_data.sync_failure.is_some() {
+            self.members_usecase
+                .save_sync_failure(member_data.discord_user_id.to_owned(), None)
+                .await?;
+            tracing::info!(
+                "member recovered from sync failures: userId: {}",
+                member_data.discord_user_id
+            );
+        }
+
+        Ok(member)
+    }
+
+    /// Marks the member in the database so that operators can tell who keeps failing,
+    /// and flags its snapshot as stale.
+    async fn record_sync_failure(&self, member_data: &MemberDataRow, cause: &anyhow::Error) {
+        let failure_count = member_data
+            .sync_failure
+            .as_ref()
+            .map_or(0, |sync_failure| sync_failure.failure_count)
+            .saturating_add(1);
+        let sync_failure = SyncFailure {
+            failure_count,
+            last_error: format!("{:#}", cause),
+            last_failed_at: Utc::now(),
+        };
+
+        if let Err(err) = self
+            .members_usecase
+            .save_sync_failure(member_data.discord_user_id.to_owned(), Some(sync_failure))
+            .await
+        {
+            tracing::warn!("could not record sync failure: {}", err);
+        }
+        let needs_reauth_since = match cause.downcast_ref::<OAuth2Error>() {
+            Some(OAuth2Error::AuthorizationRevoked { since }) => Some(*since),
+            _ => member_data.needs_reauth_since,
+        };
+        if let Err(err) = self
+            .members_usecase
+            .mark_member_snapshot_stale(&member_data.discord_user_id, needs_reauth_since)
+            .await
+        {
+            tracing::warn!("could not mark member snapshot as stale: {}", err);
+        }
+    }
+
+    async fn _get_member(
+        &self,
+        member_data: &MemberDataRow,
+        guild_roles: Option<&[Role]>,
+    ) -> anyhow::Result<MemberListRow> {
         let user_access_token = self
             .oauth2_usecase
-            .refresh_token(&member_data.discord_user_id)
+            .get_access_token(&member_data.discord_user_id)
             .await?;
         let user_http = Http::new(&format!("Bearer {}", user_access_token.secret().as_str()));
-        let bot_http = Http::new(&self.bot_token);
 
         let connections = user_http
             .get_user_connections()
             .await
             .context("could not fetch user connections from discord oauth2 server")?;
         let highest_role = self
-            .get_highest_role(&bot_http, member_data.discord_user_id.parse()?)
+            .get_highest_role(guild_roles, member_data.discord_user_id.parse()?)
             .await;
 
         Ok(MemberListRow {
@@ -93,12 +402,19 @@ where
                 name: role.name.to_owned(),
                 color: role.colour.hex(),
             }),
+            synced_at: Utc::now(),
+            stale: false,
+            needs_reauth_since: member_data.needs_reauth_since,
+            inactive_since: member_data.inactive_since,
+            registered_at: member_data.registered_at,
+            updated_at: member_data.updated_at,
+            last_token_refresh_at: member_data.last_token_refresh_at,
         })
     }
 
-    #[tracing::instrument(skip(self, http))]
-    async fn get_highest_role(&self, http: &Http, member_id: u64) -> Option<Role> {
-        let guild_roles = http
+    #[tracing::instrument(skip(self))]
+    async fn get_guild_roles(&self) -> Option<Vec<Role>> {
+        self.bot_http
             .get_guild_roles(self.guild_id)
             .await
             .inspect(|roles| {
@@ -107,8 +423,14 @@ where
             .inspect_err(|err| {
                 tracing::warn!("could not fetch guild roles from discord: {}", err);
             })
-            .ok()?;
-        let member = http
+            .ok()
+    }
+
+    #[tracing::instrument(skip(self, guild_roles))]
+    async fn get_highest_role(&self, guild_roles: Option<&[Role]>, member_id: u64) -> Option<Role> {
+        let guild_roles = guild_roles?;
+        let member = self
+            .bot_http
             .get_member(self.guild_id, member_id)
             .await
             .inspect_err(|err| tracing::warn!("could not fetch guild member from discord: {}", err))
@@ -136,3 +458,161 @@ where
         highest.cloned()
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MembershipTransition {
+    Deactivate,
+    Reactivate,
+}
+
+/// How reconciling changes the membership of the member, `None` when it already matches the guild.
+fn reconcile_transition(in_guild: bool, member: &MemberDataRow) -> Option<MembershipTransition> {
+    match (in_guild, member.inactive_since) {
+        (false, None) => Some(MembershipTransition::Deactivate),
+        // a moderator deactivated the member on purpose, while it stays in the guild.
+        (true, Some(_)) if member.deactivated_by.is_none() => {
+            Some(MembershipTransition::Reactivate)
+        }
+        _ => None,
+    }
+}
+
+fn is_unknown_member(err: &serenity::Error) -> bool {
+    matches!(
+        err,
+        serenity::Error::Http(http_err) if matches!(
+            &**http_err,
+            HttpError::UnsuccessfulRequest(response)
+                if response.status_code == reqwest::StatusCode::NOT_FOUND
+        )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use serenity::http::Http;
+
+    use crate::infra::repository::memory::MemberDataRepositoryImpl;
+    use crate::infra::repository::MemberDataRepository;
+    use crate::model::MemberDataRow;
+    use crate::usecase::audit::{Actor, AuditLogUseCase};
+    use crate::usecase::members::{DisplayNamePolicy, MembersUseCase};
+
+    use super::{reconcile_transition, MembershipTransition};
+
+    fn member(inactive: bool, deactivated_by: Option<&str>) -> MemberDataRow {
+        MemberDataRow {
+            discord_user_id: "100".to_owned(),
+            inactive_since: inactive.then(Utc::now),
+            deactivated_by: deactivated_by.map(ToOwned::to_owned),
+            ..MemberDataRow::default()
+        }
+    }
+
+    fn members_usecase(
+        repository: &MemberDataRepositoryImpl,
+    ) -> MembersUseCase<MemberDataRepositoryImpl> {
+        // no request is made, as the audit log is not mirrored to any channel.
+        let audit_log = AuditLogUseCase::new(repository.clone(), Arc::new(Http::new("")), None);
+
+        MembersUseCase::new(
+            repository.clone(),
+            DisplayNamePolicy::new(32, Vec::new(), Vec::new()),
+            audit_log,
+        )
+    }
+
+    #[test]
+    fn members_who_left_are_deactivated() {
+        assert_eq!(
+            reconcile_transition(false, &member(false, None)),
+            Some(MembershipTransition::Deactivate)
+        );
+        assert_eq!(reconcile_transition(false, &member(true, None)), None);
+        assert_eq!(
+            reconcile_transition(false, &member(true, Some("900"))),
+            None
+        );
+    }
+
+    #[test]
+    fn members_who_came_back_are_reactivated_unless_a_moderator_deactivated_them() {
+        assert_eq!(
+            reconcile_transition(true, &member(true, None)),
+            Some(MembershipTransition::Reactivate)
+        );
+        assert_eq!(reconcile_transition(true, &member(true, Some("900"))), None);
+        assert_eq!(reconcile_transition(true, &member(false, None)), None);
+    }
+
+    #[tokio::test]
+    async fn only_moderators_make_deactivations_sticky() -> anyhow::Result<()> {
+        let repository = MemberDataRepositoryImpl::new();
+        repository.save_members(vec![member(false, None)]).await?;
+        let usecase = members_usecase(&repository);
+
+        let moderator = Actor::Moderator("900".to_owned());
+        assert!(
+            usecase
+                .save_inactive_since("100".to_owned(), Some(Utc::now()), &moderator)
+                .await?
+        );
+        let deactivated = repository.get_member("100").await?;
+        assert_eq!(deactivated.deactivated_by.as_deref(), Some("900"));
+        assert_eq!(reconcile_transition(true, &deactivated), None);
+
+        assert!(
+            usecase
+                .save_inactive_since("100".to_owned(), None, &moderator)
+                .await?
+        );
+        let reactivated = repository.get_member("100").await?;
+        assert!(reactivated.inactive_since.is_none());
+        assert!(reactivated.deactivated_by.is_none());
+
+        assert!(
+            usecase
+                .save_inactive_since("100".to_owned(), Some(Utc::now()), &Actor::System)
+                .await?
+        );
+        let left = repository.get_member("100").await?;
+        assert!(left.deactivated_by.is_none());
+        assert_eq!(
+            reconcile_transition(true, &left),
+            Some(MembershipTransition::Reactivate)
+        );
+
+        // every change is recorded in the audit log, the ones by the moderator as overrides.
+        assert_eq!(
+            repository
+                .get_audit_log(Some("100"), false, 10)
+                .await?
+                .len(),
+            3
+        );
+        assert_eq!(
+            repository.get_audit_log(Some("100"), true, 10).await?.len(),
+            2
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unregistered_members_are_not_deactivated() -> anyhow::Result<()> {
+        let repository = MemberDataRepositoryImpl::new();
+        let usecase = members_usecase(&repository);
+
+        assert!(
+            !usecase
+                .save_inactive_since("100".to_owned(), Some(Utc::now()), &Actor::System)
+                .await?
+        );
+        assert!(repository.get_audit_log(None, false, 10).await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,21 +1,43 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use ::oauth2::basic::BasicClient;
+use ::oauth2::{AuthUrl, ClientId, ClientSecret, RedirectUrl, RevocationUrl, TokenUrl};
+use anyhow::Context as _;
+use chrono::Duration;
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
 use serenity::prelude::TypeMapKey;
 
+use crate::config::{
+    ArchiveConfig, Config, DisplayNameConfig, OAuth2Config, StorageBackend, TokenEncryptionConfig,
+};
+use crate::infra::repository::encrypted::{self, TokenCipher};
+use crate::infra::repository::{
+    migration, DynMemberDataRepository, DynOAuth2Repository, MemberDataRepository, OAuth2Repository,
+};
 use crate::service::members::MembersService;
 
-use self::members::MembersUseCase;
+use self::archive::ArchiveUseCase;
+use self::audit::AuditLogUseCase;
+use self::members::{DisplayNamePolicy, MembersUseCase};
 use self::oauth2::OAuth2UseCase;
 
+pub(crate) mod archive;
+pub(crate) mod audit;
 pub(crate) mod firebase;
 pub(crate) mod members;
+pub(crate) mod memory;
 pub(crate) mod oauth2;
+pub(crate) mod postgres;
+pub(crate) mod sqlite;
 
 #[derive(Clone)]
 pub(crate) struct UseCaseContainer<MR: Clone, OR: Clone> {
     pub(crate) members: MembersUseCase<MR>,
     pub(crate) oauth2: OAuth2UseCase<MR, OR>,
     pub(crate) members_service: MembersService<MR, OR>,
+    pub(crate) audit_log: AuditLogUseCase<MR>,
 }
 
 impl<UR, OR> TypeMapKey for UseCaseContainer<UR, OR>
@@ -25,3 +47,196 @@ where
 {
     type Value = Arc<Self>;
 }
+
+impl<MR, OR> UseCaseContainer<MR, OR>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    pub(crate) fn new(
+        members_repository: MR,
+        oauth2_repository: OR,
+        config: &Config,
+    ) -> anyhow::Result<Self> {
+        let oauth2_client = oauth2_client(&config.oauth2)?;
+        let csrf_token_ttl = Duration::seconds(config.oauth2.csrf_token_ttl_secs);
+
+        let guild_id = config.discord.guild_id;
+        // shared so that every request made with the bot token goes through the same rate limiter.
+        let bot_http = Arc::new(Http::new(&config.discord.token));
+
+        let audit_log_usecase = AuditLogUseCase::new(
+            members_repository.clone(),
+            Arc::clone(&bot_http),
+            config.discord.audit_log_channel_id.map(ChannelId),
+        );
+        let members_usecase = MembersUseCase::new(
+            members_repository.clone(),
+            display_name_policy(&config.display_name),
+            audit_log_usecase.clone(),
+        );
+        let oauth2_usecase = OAuth2UseCase::new(
+            oauth2_client,
+            members_repository,
+            oauth2_repository,
+            csrf_token_ttl,
+            guild_id,
+            config.discord.required_role_ids.clone(),
+            audit_log_usecase.clone(),
+        );
+        let members_service = MembersService::new(
+            members_usecase.clone(),
+            oauth2_usecase.clone(),
+            guild_id,
+            bot_http,
+            config.jobs.member_sync_concurrency,
+            std::time::Duration::from_millis(config.jobs.member_sync_request_interval_ms),
+        )?;
+
+        Ok(Self {
+            members: members_usecase,
+            oauth2: oauth2_usecase,
+            members_service,
+            audit_log: audit_log_usecase,
+        })
+    }
+}
+
+/// Container whose repositories are chosen at startup.
+pub(crate) type DynUseCaseContainer =
+    UseCaseContainer<DynMemberDataRepository, DynOAuth2Repository>;
+
+pub(crate) type DynArchiveUseCase = ArchiveUseCase<DynMemberDataRepository, DynOAuth2Repository>;
+
+pub(crate) type Repositories = (DynMemberDataRepository, DynOAuth2Repository);
+
+/// Builds usecases backed by the storage selected with `STORAGE_BACKEND` (`firestore` by default).
+pub(crate) async fn get_usecases(config: &Config) -> anyhow::Result<Arc<DynUseCaseContainer>> {
+    let (members_repository, oauth2_repository) = get_repositories(config).await?;
+
+    if config.storage.backend == StorageBackend::Memory {
+        // the in-memory storage starts empty, so there is no other run to leave the migrations to.
+        migration::run_migrations(&members_repository).await?;
+    } else {
+        let pending = migration::count_pending_migrations(&members_repository).await?;
+        if pending > 0 {
+            tracing::warn!(
+                "{} data migrations are pending, run `migrate` to apply them",
+                pending
+            );
+        }
+    }
+
+    Ok(Arc::new(UseCaseContainer::new(
+        members_repository,
+        oauth2_repository,
+        config,
+    )?))
+}
+
+/// Builds only the archive usecase, which needs neither the discord nor the oauth2 secrets.
+pub(crate) async fn get_archive_usecase(config: &Config) -> anyhow::Result<DynArchiveUseCase> {
+    let (members_repository, oauth2_repository) = get_repositories(config).await?;
+
+    Ok(ArchiveUseCase::new(
+        members_repository,
+        oauth2_repository,
+        archive_cipher(&config.archive)?,
+    ))
+}
+
+/// Opens the storage selected with `STORAGE_BACKEND`, encrypting the tokens if any key is configured.
+pub(crate) async fn get_repositories(config: &Config) -> anyhow::Result<Repositories> {
+    let storage = &config.storage;
+    tracing::info!("using storage backend {:?}", storage.backend);
+
+    let (members_repository, oauth2_repository) = match storage.backend {
+        StorageBackend::Firestore => {
+            firebase::get_firebase_repositories(&storage.firestore).await?
+        }
+        StorageBackend::Memory => memory::get_memory_repositories()?,
+        StorageBackend::Sqlite => sqlite::get_sqlite_repositories(storage).await?,
+        StorageBackend::Postgres => postgres::get_postgres_repositories(storage).await?,
+    };
+
+    let members_repository: DynMemberDataRepository = match token_cipher(&config.token_encryption)?
+    {
+        Some(cipher) => Arc::new(encrypted::MemberDataRepositoryImpl::new(
+            members_repository,
+            cipher,
+        )),
+        None => {
+            tracing::warn!(
+                "TOKEN_ENCRYPTION_KEYS is not set, oauth2 tokens are stored as plaintext"
+            );
+            members_repository
+        }
+    };
+
+    Ok((members_repository, oauth2_repository))
+}
+
+/// Tokens are stored as plaintext unless any encryption key is configured.
+fn token_cipher(config: &TokenEncryptionConfig) -> anyhow::Result<Option<TokenCipher>> {
+    if config.keys.is_empty() {
+        return Ok(None);
+    }
+
+    TokenCipher::new(config.keys.clone(), config.primary_key_id.clone())
+        .map(Some)
+        .context("could not initialize token cipher")
+        .inspect_err(|err| tracing::error!("{:#}", err))
+}
+
+const ARCHIVE_KEY_ID: &str = "archive";
+
+fn archive_cipher(config: &ArchiveConfig) -> anyhow::Result<Option<TokenCipher>> {
+    let Some(key) = &config.encryption_key else {
+        return Ok(None);
+    };
+
+    TokenCipher::new(
+        HashMap::from([(ARCHIVE_KEY_ID.to_owned(), key.clone())]),
+        ARCHIVE_KEY_ID.to_owned(),
+    )
+    .map(Some)
+    .context("could not initialize archive cipher")
+    .inspect_err(|err| tracing::error!("{:#}", err))
+}
+
+fn display_name_policy(config: &DisplayNameConfig) -> DisplayNamePolicy {
+    DisplayNamePolicy::new(
+        config.max_length,
+        config.forbidden_characters.chars().collect(),
+        config.banned_words.clone(),
+    )
+}
+
+fn oauth2_client(config: &OAuth2Config) -> anyhow::Result<BasicClient> {
+    let auth_url = "https://discord.com/api/oauth2/authorize?response_type=code".to_string();
+    let token_url = "https://discord.com/api/oauth2/token".to_string();
+    let revocation_url = "https://discord.com/api/oauth2/token/revoke".to_string();
+
+    Ok(BasicClient::new(
+        ClientId::new(config.client_id.clone()),
+        Some(ClientSecret::new(config.client_secret.clone())),
+        AuthUrl::new(auth_url)
+            .context("could not parse oauth2 auth-url")
+            .inspect_err(|err| tracing::error!("{}", err))?,
+        Some(
+            TokenUrl::new(token_url)
+                .context("could not parse oauth2 token-url")
+                .inspect_err(|err| tracing::error!("{}", err))?,
+        ),
+    )
+    .set_redirect_uri(
+        RedirectUrl::new(config.redirect_url.clone())
+            .context("could not parse oauth2 redirect-url")
+            .inspect_err(|err| tracing::error!("{}", err))?,
+    )
+    .set_revocation_uri(
+        RevocationUrl::new(revocation_url)
+            .context("could not parse oauth2 revocation-url")
+            .inspect_err(|err| tracing::error!("{}", err))?,
+    ))
+}
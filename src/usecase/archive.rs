@@ -0,0 +1,649 @@
+use anyhow::{bail, ensure, Context as _};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt as _, AsyncWrite, AsyncWriteExt as _, Lines};
+
+use crate::infra::repository::encrypted::{associated_data, is_encrypted, TokenCipher};
+use crate::infra::repository::{migration, MemberDataRepository, OAuth2Repository};
+use crate::model::{AuditLogEntry, MemberDataRow, MemberListRow};
+
+const ARCHIVE_FORMAT: &str = "members-db";
+/// Bumped whenever a record changes incompatibly. Archives of other versions are refused.
+const ARCHIVE_VERSION: u32 = 1;
+/// Number of records read from the repositories at once, so that an export never holds them all.
+const PAGE_SIZE: u32 = 100;
+
+/// One line of an archive. Every archive starts with a header.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+enum ArchiveRecord {
+    Header(ArchiveHeader),
+    Member(MemberDataRow),
+    Snapshot(MemberListRow),
+    AuditLog(AuditLogEntry),
+    CsrfToken(ArchivedCsrfToken),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchiveHeader {
+    format: String,
+    version: u32,
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    exported_at: DateTime<Utc>,
+    /// Whether the oauth2 tokens and the pkce-verifiers are encrypted with `ARCHIVE_ENCRYPTION_KEY`.
+    tokens_encrypted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedCsrfToken {
+    csrf_token: String,
+    pkce_verifier: String,
+    #[serde(with = "firestore::serialize_as_timestamp")]
+    expires_at: DateTime<Utc>,
+    #[serde(default)]
+    requested_by: Option<String>,
+}
+
+/// Number of records written to or restored from an archive.
+#[derive(Debug, Default)]
+pub(crate) struct ArchiveSummary {
+    pub(crate) members: usize,
+    pub(crate) snapshots: usize,
+    pub(crate) audit_log_entries: usize,
+    pub(crate) csrf_tokens: usize,
+}
+
+/// Copies every record between a JSON Lines archive and the repositories, whatever their backend is.
+#[derive(Clone)]
+pub(crate) struct ArchiveUseCase<MR: Clone, OR: Clone> {
+    members_repository: MR,
+    oauth2_repository: OR,
+    /// Tokens are archived as plaintext unless it is configured.
+    cipher: Option<TokenCipher>,
+}
+
+impl<MR, OR> ArchiveUseCase<MR, OR>
+where
+    MR: MemberDataRepository + Clone,
+    OR: OAuth2Repository + Clone,
+{
+    pub(crate) fn new(
+        members_repository: MR,
+        oauth2_repository: OR,
+        cipher: Option<TokenCipher>,
+    ) -> Self {
+        Self {
+            members_repository,
+            oauth2_repository,
+            cipher,
+        }
+    }
+
+    /// Writes the header followed by every record, one per line.
+    /// Records are read page by page, so that memory stays bounded however large the data is.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn export(
+        &self,
+        writer: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> anyhow::Result<ArchiveSummary> {
+        if self.cipher.is_none() {
+            tracing::warn!(
+                "ARCHIVE_ENCRYPTION_KEY is not set, oauth2 tokens are archived as plaintext"
+            );
+        }
+
+        let mut summary = ArchiveSummary::default();
+        write_record(
+            writer,
+            &ArchiveRecord::Header(ArchiveHeader {
+                format: ARCHIVE_FORMAT.to_owned(),
+                version: ARCHIVE_VERSION,
+                exported_at: Utc::now(),
+                tokens_encrypted: self.cipher.is_some(),
+            }),
+        )
+        .await?;
+
+        let mut after_member = None::<String>;
+        loop {
+            let members = self
+                .members_repository
+                .get_members_page(after_member.as_deref(), PAGE_SIZE)
+                .await
+                .context("could not get members data from database")
+                .inspect_err(|err| tracing::error!("{}", err))?;
+            let Some(last) = members.last() else {
+                break;
+            };
+            after_member = Some(last.discord_user_id.clone());
+
+            for mut member in members {
+                if let Some(cipher) = &self.cipher {
+                    encrypt_tokens(cipher, &mut member)?;
+                }
+                write_record(writer, &ArchiveRecord::Member(member)).await?;
+                summary.members += 1;
+            }
+        }
+
+        let mut after_snapshot = None::<String>;
+        loop {
+            let snapshots = self
+                .members_repository
+                .get_member_snapshots_page(after_snapshot.as_deref(), PAGE_SIZE)
+                .await
+                .context("could not get member snapshots from database")
+                .inspect_err(|err| tracing::error!("{}", err))?;
+            let Some(last) = snapshots.last() else {
+                break;
+            };
+            after_snapshot = Some(last.discord_user_id.clone());
+
+            for snapshot in snapshots {
+                write_record(writer, &ArchiveRecord::Snapshot(snapshot)).await?;
+                summary.snapshots += 1;
+            }
+        }
+
+        // oldest first, so that they are appended in order on import.
+        let mut offset: u32 = 0;
+        loop {
+            let audit_log = self
+                .members_repository
+                .get_audit_log_page(offset, PAGE_SIZE)
+                .await
+                .context("could not get audit log from database")
+                .inspect_err(|err| tracing::error!("{}", err))?;
+            if audit_log.is_empty() {
+                break;
+            }
+            offset = offset.saturating_add(u32::try_from(audit_log.len())?);
+
+            for entry in audit_log {
+                write_record(writer, &ArchiveRecord::AuditLog(entry)).await?;
+                summary.audit_log_entries += 1;
+            }
+        }
+
+        let csrf_tokens = self
+            .oauth2_repository
+            .get_all_csrf_tokens()
+            .await
+            .context("could not get csrf-tokens from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+        for (csrf_token, data) in csrf_tokens {
+            let pkce_verifier = match &self.cipher {
+                Some(cipher) => cipher.encrypt(
+                    &data.pkce_verifier,
+                    &associated_data(&csrf_token, "pkce_verifier"),
+                )?,
+                None => data.pkce_verifier,
+            };
+            write_record(
+                writer,
+                &ArchiveRecord::CsrfToken(ArchivedCsrfToken {
+                    csrf_token,
+                    pkce_verifier,
+                    expires_at: data.expires_at,
+                    requested_by: data.requested_by,
+                }),
+            )
+            .await?;
+            summary.csrf_tokens += 1;
+        }
+
+        writer.flush().await.context("could not write archive")?;
+        tracing::info!(
+            "exported {} members, {} snapshots, {} audit log entries and {} csrf-tokens",
+            summary.members,
+            summary.snapshots,
+            summary.audit_log_entries,
+            summary.csrf_tokens
+        );
+
+        Ok(summary)
+    }
+
+    /// Restores every record of the archive, overwriting the ones which already exist.
+    /// Importing the same archive again leaves the data as it is.
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn import(
+        &self,
+        reader: &mut (dyn AsyncBufRead + Unpin + Send),
+    ) -> anyhow::Result<ArchiveSummary> {
+        let mut lines = reader.lines();
+        let mut lines_read = 0;
+
+        let Some((_, header)) = next_record(&mut lines, &mut lines_read).await? else {
+            bail!("the archive is empty");
+        };
+        let ArchiveRecord::Header(header) = header else {
+            bail!("the archive does not start with a header");
+        };
+        let archive_cipher = self.archive_cipher(&header)?;
+
+        let mut summary = ArchiveSummary::default();
+        while let Some((line_number, record)) = next_record(&mut lines, &mut lines_read).await? {
+            match record {
+                ArchiveRecord::Header(_) => bail!("line {line_number} is another header"),
+                ArchiveRecord::Member(mut member) => {
+                    if let Some(cipher) = archive_cipher {
+                        decrypt_tokens(cipher, &mut member)
+                            .with_context(|| format!("could not decrypt line {line_number}"))?;
+                    }
+                    self.restore_member(member).await?;
+                    summary.members += 1;
+                }
+                ArchiveRecord::Snapshot(snapshot) => {
+                    self.members_repository
+                        .save_member_snapshot(snapshot)
+                        .await
+                        .context("could not save member snapshot to database")
+                        .inspect_err(|err| tracing::error!("{}", err))?;
+                    summary.snapshots += 1;
+                }
+                ArchiveRecord::AuditLog(entry) => {
+                    // the audit log is append-only, so entries restored before are skipped.
+                    if self.is_audit_log_restored(&entry).await? {
+                        continue;
+                    }
+                    self.members_repository
+                        .append_audit_log(entry)
+                        .await
+                        .context("could not append audit log to database")
+                        .inspect_err(|err| tracing::error!("{}", err))?;
+                    summary.audit_log_entries += 1;
+                }
+                ArchiveRecord::CsrfToken(mut token) => {
+                    if token.expires_at <= Utc::now() {
+                        continue;
+                    }
+                    if let Some(cipher) = archive_cipher {
+                        token.pkce_verifier = decrypt(
+                            cipher,
+                            &token.pkce_verifier,
+                            &associated_data(&token.csrf_token, "pkce_verifier"),
+                        )
+                        .with_context(|| format!("could not decrypt line {line_number}"))?;
+                    }
+                    self.oauth2_repository
+                        .save_csrf_token(
+                            token.csrf_token,
+                            token.pkce_verifier,
+                            token.expires_at,
+                            token.requested_by,
+                        )
+                        .await
+                        .context("could not save csrf-token to database")
+                        .inspect_err(|err| tracing::error!("{}", err))?;
+                    summary.csrf_tokens += 1;
+                }
+            }
+        }
+        tracing::info!(
+            "imported {} members, {} snapshots, {} audit log entries and {} csrf-tokens",
+            summary.members,
+            summary.snapshots,
+            summary.audit_log_entries,
+            summary.csrf_tokens
+        );
+
+        Ok(summary)
+    }
+
+    fn archive_cipher(&self, header: &ArchiveHeader) -> anyhow::Result<Option<&TokenCipher>> {
+        ensure!(
+            header.format == ARCHIVE_FORMAT,
+            "the file is not an archive of members-db"
+        );
+        ensure!(
+            header.version == ARCHIVE_VERSION,
+            "archive version {} is not supported, expected {}",
+            header.version,
+            ARCHIVE_VERSION
+        );
+        if !header.tokens_encrypted {
+            return Ok(None);
+        }
+
+        self.cipher
+            .as_ref()
+            .map(Some)
+            .context("the archive is encrypted, but ARCHIVE_ENCRYPTION_KEY is not set")
+    }
+
+    /// Looks the entry up among the ones of the member recorded within the same microsecond,
+    /// the precision every backend keeps, instead of holding the whole audit log.
+    async fn is_audit_log_restored(&self, entry: &AuditLogEntry) -> anyhow::Result<bool> {
+        let from = entry.recorded_at
+            - Duration::nanoseconds(i64::from(entry.recorded_at.timestamp_subsec_nanos() % 1000));
+        let key = audit_log_key(entry);
+
+        let restored = self
+            .members_repository
+            .get_member_audit_log_between(
+                &entry.discord_user_id,
+                from,
+                from + Duration::microseconds(1),
+            )
+            .await
+            .context("could not get audit log from database")
+            .inspect_err(|err| tracing::error!("{}", err))?
+            .iter()
+            .any(|existing| audit_log_key(existing) == key);
+
+        Ok(restored)
+    }
+
+    /// Saves the member as archived, upgrading it first if it was exported by an older version.
+    /// Nothing is recorded in the audit log, which is restored as well.
+    async fn restore_member(&self, mut member: MemberDataRow) -> anyhow::Result<()> {
+        migration::upgrade_member(&mut member);
+
+        self.members_repository
+            .save_members(vec![member])
+            .await
+            .context("could not save member data to database")
+            .inspect_err(|err| tracing::error!("{}", err))
+    }
+}
+
+/// Identifies an audit log entry across backends, which keep the timestamps in different precisions.
+type AuditLogKey = (String, &'static str, String, i64);
+
+fn audit_log_key(entry: &AuditLogEntry) -> AuditLogKey {
+    (
+        entry.discord_user_id.clone(),
+        entry.action.as_str(),
+        entry.actor.clone(),
+        entry.recorded_at.timestamp_micros(),
+    )
+}
+
+async fn write_record(
+    writer: &mut (dyn AsyncWrite + Unpin + Send),
+    record: &ArchiveRecord,
+) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(record).context("could not serialize archive record")?;
+    line.push(b'\n');
+    writer
+        .write_all(&line)
+        .await
+        .context("could not write archive")?;
+
+    Ok(())
+}
+
+/// Reads up to the next line which is not blank, returning its line number with the record.
+async fn next_record<R>(
+    lines: &mut Lines<R>,
+    line_number: &mut usize,
+) -> anyhow::Result<Option<(usize, ArchiveRecord)>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    loop {
+        *line_number += 1;
+        let Some(line) = lines
+            .next_line()
+            .await
+            .with_context(|| format!("could not read line {line_number} of archive"))?
+        else {
+            return Ok(None);
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        return serde_json::from_str(&line)
+            .map(|record| Some((*line_number, record)))
+            .with_context(|| format!("line {line_number} is not a valid archive record"))
+            .inspect_err(|err| tracing::error!("{:#}", err));
+    }
+}
+
+fn encrypt_tokens(cipher: &TokenCipher, member: &mut MemberDataRow) -> anyhow::Result<()> {
+    member.oauth2.access_token = cipher.encrypt(
+        &member.oauth2.access_token,
+        &associated_data(&member.discord_user_id, "access_token"),
+    )?;
+    member.oauth2.refresh_token = cipher.encrypt(
+        &member.oauth2.refresh_token,
+        &associated_data(&member.discord_user_id, "refresh_token"),
+    )?;
+
+    Ok(())
+}
+
+fn decrypt_tokens(cipher: &TokenCipher, member: &mut MemberDataRow) -> anyhow::Result<()> {
+    member.oauth2.access_token = decrypt(
+        cipher,
+        &member.oauth2.access_token,
+        &associated_data(&member.discord_user_id, "access_token"),
+    )?;
+    member.oauth2.refresh_token = decrypt(
+        cipher,
+        &member.oauth2.refresh_token,
+        &associated_data(&member.discord_user_id, "refresh_token"),
+    )?;
+
+    Ok(())
+}
+
+/// Unlike the repositories, refuses plaintext, which an encrypted archive never contains.
+fn decrypt(cipher: &TokenCipher, archived: &str, aad: &str) -> anyhow::Result<String> {
+    ensure!(
+        is_encrypted(archived),
+        "the archive is encrypted, but holds a plaintext value"
+    );
+
+    Ok(cipher.decrypt(archived, aad)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{Duration, Utc};
+
+    use crate::infra::repository::encrypted::TokenCipher;
+    use crate::infra::repository::memory::{MemberDataRepositoryImpl, OAuth2RepositoryImpl};
+    use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
+    use crate::model::{
+        AuditAction, AuditLogEntry, MemberDataRow, MemberListRow, MemberOAuth2Data,
+        MEMBER_DATA_SCHEMA_VERSION,
+    };
+
+    use super::{ArchiveHeader, ArchiveRecord, ArchiveUseCase, ARCHIVE_FORMAT, ARCHIVE_VERSION};
+
+    type MemoryArchiveUseCase = ArchiveUseCase<MemberDataRepositoryImpl, OAuth2RepositoryImpl>;
+
+    fn archive_usecase() -> anyhow::Result<MemoryArchiveUseCase> {
+        let cipher = TokenCipher::new(
+            HashMap::from([("archive".to_owned(), vec![1; 32])]),
+            "archive".to_owned(),
+        )?;
+
+        Ok(ArchiveUseCase::new(
+            MemberDataRepositoryImpl::new(),
+            OAuth2RepositoryImpl::new(),
+            Some(cipher),
+        ))
+    }
+
+    async fn populate(usecase: &MemoryArchiveUseCase) -> anyhow::Result<()> {
+        let now = Utc::now();
+        usecase
+            .members_repository
+            .save_members(vec![MemberDataRow {
+                discord_user_id: "100".to_owned(),
+                display_name: Some("member".to_owned()),
+                oauth2: MemberOAuth2Data {
+                    access_token: "access".to_owned(),
+                    refresh_token: "refresh".to_owned(),
+                    expires_at: Some(now + Duration::days(7)),
+                },
+                registered_at: Some(now),
+                updated_at: Some(now),
+                last_token_refresh_at: Some(now),
+                schema_version: MEMBER_DATA_SCHEMA_VERSION,
+                ..MemberDataRow::default()
+            }])
+            .await?;
+        usecase
+            .members_repository
+            .save_member_snapshot(MemberListRow {
+                discord_user_id: "100".to_owned(),
+                display_name: Some("member".to_owned()),
+                twitter: vec!["member".to_owned()],
+                github: Vec::new(),
+                role: None,
+                synced_at: now,
+                stale: false,
+                needs_reauth_since: None,
+                inactive_since: None,
+                registered_at: Some(now),
+                updated_at: Some(now),
+                last_token_refresh_at: Some(now),
+            })
+            .await?;
+        usecase
+            .members_repository
+            .append_audit_log(AuditLogEntry {
+                discord_user_id: "100".to_owned(),
+                action: AuditAction::Registered,
+                actor: "member:100".to_owned(),
+                recorded_at: now,
+                admin_override: false,
+                before: None,
+                after: None,
+            })
+            .await?;
+        usecase
+            .oauth2_repository
+            .save_csrf_token(
+                "csrf".to_owned(),
+                "pkce".to_owned(),
+                now + Duration::minutes(10),
+                Some("100".to_owned()),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn export(usecase: &MemoryArchiveUseCase) -> anyhow::Result<Vec<u8>> {
+        let mut archive = Vec::new();
+        usecase.export(&mut archive).await?;
+
+        Ok(archive)
+    }
+
+    #[tokio::test]
+    async fn archive_restores_every_record_with_the_tokens() -> anyhow::Result<()> {
+        let source = archive_usecase()?;
+        populate(&source).await?;
+        let archive = export(&source).await?;
+        // the tokens and the pkce-verifiers are encrypted with the archive key.
+        let text = String::from_utf8(archive.clone())?;
+        assert!(!text.contains("\"access\""));
+        assert!(!text.contains("\"pkce\""));
+
+        let destination = archive_usecase()?;
+        let summary = destination.import(&mut archive.as_slice()).await?;
+
+        assert_eq!(summary.members, 1);
+        assert_eq!(summary.snapshots, 1);
+        assert_eq!(summary.audit_log_entries, 1);
+        assert_eq!(summary.csrf_tokens, 1);
+        let member = destination.members_repository.get_member("100").await?;
+        assert_eq!(member.oauth2.access_token, "access");
+        assert_eq!(member.oauth2.refresh_token, "refresh");
+        assert_eq!(member.display_name.as_deref(), Some("member"));
+        let csrf_tokens = destination.oauth2_repository.get_all_csrf_tokens().await?;
+        assert!(csrf_tokens
+            .iter()
+            .all(|(_, data)| data.pkce_verifier == "pkce"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn importing_an_archive_again_changes_nothing() -> anyhow::Result<()> {
+        let source = archive_usecase()?;
+        populate(&source).await?;
+        let archive = export(&source).await?;
+
+        let destination = archive_usecase()?;
+        destination.import(&mut archive.as_slice()).await?;
+        let summary = destination.import(&mut archive.as_slice()).await?;
+
+        assert_eq!(summary.audit_log_entries, 0);
+        let repository = &destination.members_repository;
+        assert_eq!(repository.get_all_members().await?.len(), 1);
+        assert_eq!(repository.get_all_member_snapshots().await?.len(), 1);
+        assert_eq!(repository.get_audit_log(None, false, 10).await?.len(), 1);
+        assert_eq!(
+            destination
+                .oauth2_repository
+                .get_all_csrf_tokens()
+                .await?
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn archives_without_a_header_are_refused() -> anyhow::Result<()> {
+        let usecase = archive_usecase()?;
+        populate(&usecase).await?;
+        let mut archive = export(&usecase).await?;
+        let header_end = archive
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(0, |position| position + 1);
+        archive.drain(..header_end);
+
+        assert!(usecase.import(&mut archive.as_slice()).await.is_err());
+        assert!(usecase.import(&mut b"".as_slice()).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plaintext_in_encrypted_archives_is_refused() -> anyhow::Result<()> {
+        let usecase = archive_usecase()?;
+        let mut archive = Vec::new();
+        for record in [
+            ArchiveRecord::Header(ArchiveHeader {
+                format: ARCHIVE_FORMAT.to_owned(),
+                version: ARCHIVE_VERSION,
+                exported_at: Utc::now(),
+                tokens_encrypted: true,
+            }),
+            ArchiveRecord::Member(MemberDataRow {
+                discord_user_id: "100".to_owned(),
+                oauth2: MemberOAuth2Data {
+                    access_token: "access".to_owned(),
+                    refresh_token: "refresh".to_owned(),
+                    expires_at: None,
+                },
+                ..MemberDataRow::default()
+            }),
+        ] {
+            archive.extend(serde_json::to_vec(&record)?);
+            archive.push(b'\n');
+        }
+
+        assert!(usecase.import(&mut archive.as_slice()).await.is_err());
+        assert!(usecase
+            .members_repository
+            .get_all_members()
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use chrono::Utc;
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+
+use crate::infra::repository::{MemberDataRepository, RepositoryError};
+use crate::model::{AuditAction, AuditLogEntry, AuditedMemberData};
+
+/// Who made a change recorded in the audit log.
+#[derive(Debug, Clone)]
+pub(crate) enum Actor {
+    /// Background jobs and discord events.
+    System,
+    /// The member changing its own data.
+    Member(String),
+    /// A moderator running an admin command.
+    Moderator(String),
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Actor::System => write!(f, "system"),
+            Actor::Member(id) => write!(f, "member:{id}"),
+            Actor::Moderator(id) => write!(f, "moderator:{id}"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AuditLogUseCase<R: Clone> {
+    member_data_repository: R,
+    /// Shared with the members service, so that the mirrored messages go through its rate limiter.
+    bot_http: Arc<Http>,
+    /// Every entry is posted to the channel too, when it is configured.
+    mirror_channel_id: Option<ChannelId>,
+}
+
+impl<R: MemberDataRepository + Clone> AuditLogUseCase<R> {
+    pub(crate) fn new(
+        member_data_repository: R,
+        bot_http: Arc<Http>,
+        mirror_channel_id: Option<ChannelId>,
+    ) -> Self {
+        Self {
+            member_data_repository,
+            bot_http,
+            mirror_channel_id,
+        }
+    }
+
+    /// Returns the member data to be recorded, `None` when the member is not registered.
+    pub(crate) async fn capture(&self, discord_user_id: &str) -> Option<AuditedMemberData> {
+        match self
+            .member_data_repository
+            .get_member(discord_user_id)
+            .await
+        {
+            Ok(member) => Some(AuditedMemberData::from(&member)),
+            Err(RepositoryError::NotFound { .. }) => None,
+            Err(err) => {
+                tracing::warn!(
+                    "could not get member data to audit, recording it as missing: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Failures are logged rather than returned, as the change itself has already been made.
+    #[tracing::instrument(skip(self, before, after))]
+    pub(crate) async fn record(
+        &self,
+        discord_user_id: &str,
+        action: AuditAction,
+        actor: &Actor,
+        before: Option<AuditedMemberData>,
+        after: Option<AuditedMemberData>,
+    ) {
+        let entry = AuditLogEntry {
+            discord_user_id: discord_user_id.to_owned(),
+            action,
+            actor: actor.to_string(),
+            recorded_at: Utc::now(),
+            admin_override: matches!(actor, Actor::Moderator(_)),
+            before,
+            after,
+        };
+
+        if let Err(err) = self
+            .member_data_repository
+            .append_audit_log(entry.clone())
+            .await
+        {
+            tracing::error!(
+                "could not append audit log entry: {:?}, error: {}",
+                entry,
+                err
+            );
+        }
+        self.mirror(&entry).await;
+    }
+
+    async fn mirror(&self, entry: &AuditLogEntry) {
+        let Some(channel_id) = self.mirror_channel_id else {
+            return;
+        };
+
+        let content = format!(
+            "`{}` <@{}> by `{}`\nbefore: `{:?}`\nafter: `{:?}`",
+            entry.action.as_str(),
+            entry.discord_user_id,
+            entry.actor,
+            entry.before,
+            entry.after
+        );
+        if let Err(err) = channel_id
+            .send_message(&*self.bot_http, |m| {
+                m.content(content)
+                    .allowed_mentions(|mentions| mentions.empty_parse())
+            })
+            .await
+        {
+            tracing::warn!("could not mirror audit log entry to discord: {}", err);
+        }
+    }
+
+    /// Returns up to `limit` entries newest first, only of the member if `discord_user_id` is given,
+    /// and only the admin overrides if `admin_overrides_only` is set.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn get_entries(
+        &self,
+        discord_user_id: Option<&str>,
+        admin_overrides_only: bool,
+        limit: u32,
+    ) -> anyhow::Result<Vec<AuditLogEntry>> {
+        self.member_data_repository
+            .get_audit_log(discord_user_id, admin_overrides_only, limit)
+            .await
+            .context("could not get audit log from database")
+            .inspect_err(|err| tracing::error!("{}", err))
+    }
+}
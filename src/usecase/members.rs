@@ -1,46 +1,245 @@
 use crate::infra::repository::{MemberDataRepository, RepositoryError};
-use crate::model::MemberDataRow;
+use crate::model::{AuditAction, MemberDataRow, MemberListRow, SyncFailure};
+use crate::usecase::audit::{Actor, AuditLogUseCase};
 use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization as _;
+
+/// Reasons why a display name is refused.
+#[derive(Debug, Error)]
+pub(crate) enum DisplayNameError {
+    #[error("the display name is empty")]
+    Empty,
+    #[error("the display name is longer than {max_length} characters")]
+    TooLong { max_length: usize },
+    #[error("the display name contains the forbidden character {character:?}")]
+    ForbiddenCharacter { character: char },
+    #[error("the display name contains a mention")]
+    Mention,
+    #[error("the display name contains a banned word")]
+    BannedWord,
+}
+
+/// Rules which display names have to follow, checked against their NFKC normalized form.
+#[derive(Clone, Debug)]
+pub(crate) struct DisplayNamePolicy {
+    /// Counted in characters rather than in bytes.
+    max_length: usize,
+    /// Forbidden on top of control, zero-width and bidirectional formatting characters.
+    forbidden_characters: Vec<char>,
+    /// Normalized and lowercased, so that they match regardless of the width and the case.
+    banned_words: Vec<String>,
+}
+
+impl DisplayNamePolicy {
+    pub(crate) fn new(
+        max_length: usize,
+        forbidden_characters: Vec<char>,
+        banned_words: Vec<String>,
+    ) -> Self {
+        Self {
+            max_length,
+            forbidden_characters,
+            banned_words: banned_words
+                .iter()
+                .map(|word| word.nfkc().collect::<String>().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns the display name to be stored, normalized and trimmed.
+    pub(crate) fn validate(&self, display_name: &str) -> Result<String, DisplayNameError> {
+        let normalized = display_name.nfkc().collect::<String>().trim().to_owned();
+
+        if normalized.is_empty() {
+            return Err(DisplayNameError::Empty);
+        }
+        if normalized.chars().count() > self.max_length {
+            return Err(DisplayNameError::TooLong {
+                max_length: self.max_length,
+            });
+        }
+        let characters = normalized.chars().collect::<Vec<_>>();
+        if let Some(character) = characters.iter().enumerate().find_map(|(position, c)| {
+            let forbidden = if *c == ZERO_WIDTH_JOINER {
+                !joins_emoji(&characters, position)
+            } else {
+                is_invisible(*c) || self.forbidden_characters.contains(c)
+            };
+            forbidden.then_some(*c)
+        }) {
+            return Err(DisplayNameError::ForbiddenCharacter { character });
+        }
+        if contains_mention(&normalized) {
+            return Err(DisplayNameError::Mention);
+        }
+
+        // words spelled out letter by letter are caught as well, but not across ordinary words,
+        // so that a banned word does not match the end of one word and the start of the next.
+        let lowercase = normalized.to_lowercase();
+        let spelled_out = spelled_out_words(&lowercase);
+        if self.banned_words.iter().any(|word| {
+            lowercase.contains(word.as_str())
+                || spelled_out
+                    .iter()
+                    .any(|spelled| spelled.contains(word.as_str()))
+        }) {
+            return Err(DisplayNameError::BannedWord);
+        }
+
+        Ok(normalized)
+    }
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Characters which are not rendered or reorder the text around them, NFKC keeps them as is.
+/// The zero width joiner is left to [`joins_emoji`].
+fn is_invisible(character: char) -> bool {
+    character.is_control()
+        || matches!(
+            character,
+            '\u{115F}'
+                | '\u{1160}'
+                | '\u{200B}'
+                | '\u{200C}'
+                | '\u{200E}'
+                | '\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{2066}'..='\u{2069}'
+                | '\u{3164}'
+                | '\u{FEFF}'
+                | '\u{FFA0}'
+        )
+}
+
+/// Whether the zero width joiner at `position` combines two emoji into one, as in the family emoji.
+fn joins_emoji(characters: &[char], position: usize) -> bool {
+    let previous = position
+        .checked_sub(1)
+        .and_then(|previous| characters.get(previous));
+    let next = characters.get(position + 1);
+
+    // the emoji before it may end with a variation selector, as in the heart on fire.
+    previous.map_or(false, |c| is_emoji(*c) || *c == '\u{FE0F}')
+        && next.map_or(false, |c| is_emoji(*c))
+}
+
+/// Approximates the pictographic characters which emoji sequences are made of.
+fn is_emoji(character: char) -> bool {
+    matches!(
+        character,
+        '\u{2300}'..='\u{23FF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+/// Runs of single characters separated by whitespace joined back together, such as `b a d`.
+fn spelled_out_words(display_name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for token in display_name.split_whitespace() {
+        if token.chars().count() == 1 {
+            current.push_str(token);
+        } else if !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+/// Mentions of everyone, users, roles and channels.
+fn contains_mention(display_name: &str) -> bool {
+    ["@everyone", "@here", "<@", "<#"]
+        .iter()
+        .any(|pattern| display_name.contains(pattern))
+}
 
 #[derive(Clone)]
 pub(crate) struct MembersUseCase<R: Clone> {
     member_data_repository: R,
+    display_name_policy: DisplayNamePolicy,
+    audit_log: AuditLogUseCase<R>,
 }
 
 impl<R: MemberDataRepository + Clone> MembersUseCase<R> {
-    pub(crate) fn new(member_data_repository: R) -> Self {
+    pub(crate) fn new(
+        member_data_repository: R,
+        display_name_policy: DisplayNamePolicy,
+        audit_log: AuditLogUseCase<R>,
+    ) -> Self {
         Self {
             member_data_repository,
+            display_name_policy,
+            audit_log,
         }
     }
 
+    /// Returns the display name as stored. Names against the policy are refused with [`DisplayNameError`].
     #[tracing::instrument(skip(self))]
     pub(crate) async fn update_member_display_name(
         &self,
         discord_user_id: String,
         new_display_name: String,
-    ) -> anyhow::Result<()> {
-        self.member_data_repository
-            .save_display_name(discord_user_id, Some(new_display_name))
-            .await
-            .context("error occurred when updating user display name")
-            .inspect_err(|err| tracing::error!("{}", err))?;
+        actor: &Actor,
+    ) -> anyhow::Result<String> {
+        let display_name = self
+            .display_name_policy
+            .validate(&new_display_name)
+            .inspect_err(|err| tracing::info!("refused display name: {}", err))?;
+
+        self.save_display_name(discord_user_id, Some(display_name.clone()), actor)
+            .await?;
         tracing::info!("updated member display name");
 
-        Ok(())
+        Ok(display_name)
     }
 
     #[tracing::instrument(skip(self))]
     pub(crate) async fn unset_member_display_name(
         &self,
         discord_user_id: String,
+        actor: &Actor,
+    ) -> anyhow::Result<()> {
+        self.save_display_name(discord_user_id, None, actor).await?;
+        tracing::info!("updated member display name to default");
+
+        Ok(())
+    }
+
+    async fn save_display_name(
+        &self,
+        discord_user_id: String,
+        display_name: Option<String>,
+        actor: &Actor,
     ) -> anyhow::Result<()> {
+        let before = self.audit_log.capture(&discord_user_id).await;
+
         self.member_data_repository
-            .save_display_name(discord_user_id, None)
+            .save_display_name(discord_user_id.clone(), display_name)
             .await
             .context("error occurred when updating user display name")
             .inspect_err(|err| tracing::error!("{}", err))?;
-        tracing::info!("updated member display name to default");
+
+        let after = self.audit_log.capture(&discord_user_id).await;
+        self.audit_log
+            .record(
+                &discord_user_id,
+                AuditAction::DisplayNameChanged,
+                actor,
+                before,
+                after,
+            )
+            .await;
 
         Ok(())
     }
@@ -71,4 +270,201 @@ impl<R: MemberDataRepository + Clone> MembersUseCase<R> {
             },
         }
     }
+
+    #[tracing::instrument(skip(self, sync_failure))]
+    pub(crate) async fn save_sync_failure(
+        &self,
+        discord_user_id: String,
+        sync_failure: Option<SyncFailure>,
+    ) -> anyhow::Result<()> {
+        self.member_data_repository
+            .save_sync_failure(discord_user_id, sync_failure)
+            .await
+            .context("could not save sync failure to database")
+            .inspect_err(|err| tracing::error!("{}", err))
+    }
+
+    /// Returns `false` when the member is not registered.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn save_inactive_since(
+        &self,
+        discord_user_id: String,
+        inactive_since: Option<DateTime<Utc>>,
+        actor: &Actor,
+    ) -> anyhow::Result<bool> {
+        let before = self.audit_log.capture(&discord_user_id).await;
+        // only a moderator keeps a member inactive regardless of its presence in the guild.
+        let deactivated_by = match (inactive_since, actor) {
+            (Some(_), Actor::Moderator(moderator_id)) => Some(moderator_id.clone()),
+            _ => None,
+        };
+
+        match self
+            .member_data_repository
+            .save_inactive_since(discord_user_id.clone(), inactive_since, deactivated_by)
+            .await
+        {
+            Ok(()) => {}
+            Err(RepositoryError::NotFound { .. }) => return Ok(false),
+            Err(err) => {
+                return Err(err)
+                    .context("could not save member activity to database")
+                    .inspect_err(|context| tracing::error!("{}", context))
+            }
+        }
+
+        let after = self.audit_log.capture(&discord_user_id).await;
+        self.audit_log
+            .record(
+                &discord_user_id,
+                AuditAction::MembershipChanged,
+                actor,
+                before,
+                after,
+            )
+            .await;
+
+        Ok(true)
+    }
+
+    /// Reflects whether the member is in the guild to its snapshot, if the member was ever synchronized.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn save_member_snapshot_inactive_since(
+        &self,
+        discord_user_id: &str,
+        inactive_since: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let Some(mut snapshot) = self.get_member_snapshot(discord_user_id).await? else {
+            return Ok(());
+        };
+
+        snapshot.inactive_since = inactive_since;
+        self.save_member_snapshot(snapshot).await
+    }
+
+    /// Flags the snapshot of the member as outdated, if the member was ever synchronized.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn mark_member_snapshot_stale(
+        &self,
+        discord_user_id: &str,
+        needs_reauth_since: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let Some(mut snapshot) = self.get_member_snapshot(discord_user_id).await? else {
+            return Ok(());
+        };
+        if snapshot.stale && snapshot.needs_reauth_since == needs_reauth_since {
+            return Ok(());
+        }
+
+        snapshot.stale = true;
+        snapshot.needs_reauth_since = needs_reauth_since;
+        self.save_member_snapshot(snapshot).await
+    }
+
+    #[tracing::instrument(skip(self, snapshot))]
+    pub(crate) async fn save_member_snapshot(&self, snapshot: MemberListRow) -> anyhow::Result<()> {
+        self.member_data_repository
+            .save_member_snapshot(snapshot)
+            .await
+            .context("could not save member snapshot to database")
+            .inspect_err(|err| tracing::error!("{}", err))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn get_all_member_snapshots(&self) -> anyhow::Result<Vec<MemberListRow>> {
+        self.member_data_repository
+            .get_all_member_snapshots()
+            .await
+            .context("could not get member snapshots from database")
+            .inspect_err(|err| tracing::error!("{}", err))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn get_member_snapshot(
+        &self,
+        discord_user_id: &str,
+    ) -> anyhow::Result<Option<MemberListRow>> {
+        let snapshot = self
+            .member_data_repository
+            .get_member_snapshot(discord_user_id)
+            .await;
+        match snapshot {
+            Ok(snapshot) => Ok(Some(snapshot)),
+            Err(err) => match err {
+                RepositoryError::NotFound { .. } => Ok(None),
+                _ => Err(err.into()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DisplayNameError, DisplayNamePolicy};
+
+    fn policy() -> DisplayNamePolicy {
+        DisplayNamePolicy::new(8, vec!['#'], vec!["ＢＡＤ".to_owned()])
+    }
+
+    #[test]
+    fn display_names_are_normalized_and_trimmed() {
+        assert_eq!(
+            policy().validate("  ｎａｍｅ  ").ok().as_deref(),
+            Some("name")
+        );
+        assert!(matches!(
+            policy().validate("   "),
+            Err(DisplayNameError::Empty)
+        ));
+        assert!(matches!(
+            policy().validate("ninechars"),
+            Err(DisplayNameError::TooLong { max_length: 8 })
+        ));
+    }
+
+    #[test]
+    fn invisible_and_forbidden_characters_are_refused() {
+        for character in ['\u{200B}', '\u{202E}', '\u{2060}', '#'] {
+            assert!(matches!(
+                policy().validate(&format!("a{character}b")),
+                Err(DisplayNameError::ForbiddenCharacter { character: found }) if found == character
+            ));
+        }
+        assert!(matches!(
+            policy().validate("<@100>"),
+            Err(DisplayNameError::Mention)
+        ));
+    }
+
+    #[test]
+    fn zero_width_joiners_are_allowed_only_inside_emoji() {
+        // the family and the heart on fire.
+        assert!(policy()
+            .validate("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}")
+            .is_ok());
+        assert!(policy()
+            .validate("\u{2764}\u{FE0F}\u{200D}\u{1F525}")
+            .is_ok());
+
+        for name in ["a\u{200D}b", "\u{1F468}\u{200D}", "\u{200D}\u{1F468}"] {
+            assert!(matches!(
+                policy().validate(name),
+                Err(DisplayNameError::ForbiddenCharacter {
+                    character: '\u{200D}'
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn banned_words_match_regardless_of_width_case_and_spelling_out() {
+        for name in ["bad", "xBADx", "b a d", "x b a d"] {
+            assert!(matches!(
+                policy().validate(name),
+                Err(DisplayNameError::BannedWord)
+            ));
+        }
+        // not across ordinary words.
+        assert!(policy().validate("ab adam").is_ok());
+    }
 }
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use crate::infra::repository::memory::{MemberDataRepositoryImpl, OAuth2RepositoryImpl};
+use crate::infra::repository::{DynMemberDataRepository, DynOAuth2Repository};
+
+use super::Repositories;
+
+/// Repositories over process-local storage. Every restart starts from an empty database.
+pub(crate) fn get_memory_repositories() -> anyhow::Result<Repositories> {
+    tracing::warn!("member data is kept in memory and will be lost on shutdown");
+
+    let members_repository: DynMemberDataRepository = Arc::new(MemberDataRepositoryImpl::new());
+    let oauth2_repository: DynOAuth2Repository = Arc::new(OAuth2RepositoryImpl::new());
+
+    Ok((members_repository, oauth2_repository))
+}
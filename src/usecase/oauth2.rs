@@ -1,19 +1,96 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
 use anyhow::Context;
-use oauth2::basic::BasicClient;
+use chrono::{DateTime, Duration, Utc};
+use oauth2::basic::{BasicClient, BasicErrorResponseType, BasicTokenResponse};
 use oauth2::reqwest::async_http_client;
 use oauth2::{
     AccessToken, AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RefreshToken,
-    Scope, TokenResponse,
+    RequestTokenError, Scope, StandardRevocableToken, TokenResponse,
 };
+use serde::Deserialize;
 use serenity::http::Http;
+use thiserror::Error;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::infra::repository::{MemberDataRepository, OAuth2Repository, RepositoryError};
+use crate::model::{AuditAction, AuditedMemberData, MemberOAuth2Data};
+use crate::usecase::audit::{Actor, AuditLogUseCase};
+
+/// Access-tokens expiring within this margin are refreshed before use.
+const ACCESS_TOKEN_EXPIRY_MARGIN_SECS: i64 = 5 * 60;
+
+/// Errors caused by the authorization itself rather than by the server.
+#[derive(Debug, Error)]
+pub(crate) enum OAuth2Error {
+    #[error("the csrf-token is unknown or has already been used")]
+    UnknownCsrfToken,
+    #[error("the csrf-token has expired")]
+    CsrfTokenExpired,
+    #[error("the authorization was revoked at {since}, the member needs to link again")]
+    AuthorizationRevoked { since: DateTime<Utc> },
+    #[error("the access-token is not accepted by discord")]
+    InvalidAccessToken,
+    #[error("the user is not a member of the guild")]
+    NotGuildMember,
+    #[error("the member does not have any of the required roles")]
+    MissingRequiredRole,
+    #[error("the auth-url was issued to another user")]
+    UnexpectedUser,
+}
+
+/// Per-member locks which keep the refresh-token of a member from being used twice at once.
+type RefreshLocks = Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>;
+
+/// Held while the tokens of a member are refreshed or revoked.
+/// The lock of the member is dropped together with the last holder, so that the locks do not pile up.
+struct RefreshGuard {
+    locks: RefreshLocks,
+    discord_user_id: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        // nobody can clone the lock out of the map meanwhile, so the count only falls from here.
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        self.guard.take();
+        if locks
+            .get(&self.discord_user_id)
+            .map_or(false, |lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.discord_user_id);
+        }
+    }
+}
 
-use crate::infra::repository::{MemberDataRepository, OAuth2Repository};
+/// Outcome of refreshing the tokens of every member.
+#[derive(Debug, Default)]
+pub(crate) struct RefreshAllSummary {
+    pub(crate) refreshed: usize,
+    /// Members whose authorization was already known to be revoked.
+    pub(crate) skipped: usize,
+    pub(crate) failed: usize,
+}
+
+/// Response of `GET /users/@me/guilds/{guild.id}/member`, which needs the `guilds.members.read` scope.
+#[derive(Debug, Deserialize)]
+struct CurrentUserGuildMember {
+    roles: Vec<String>,
+}
 
 #[derive(Clone)]
 pub(crate) struct OAuth2UseCase<MR: Clone, OR: Clone> {
     oauth2_client: BasicClient,
     members_repository: MR,
     oauth2_repository: OR,
+    csrf_token_ttl: Duration,
+    guild_id: u64,
+    /// Members need one of them to register. Empty means no role is required.
+    required_role_ids: Vec<u64>,
+    refresh_locks: RefreshLocks,
+    audit_log: AuditLogUseCase<MR>,
 }
 
 impl<MR: MemberDataRepository + Clone, OR: OAuth2Repository + Clone> OAuth2UseCase<MR, OR> {
@@ -21,17 +98,40 @@ impl<MR: MemberDataRepository + Clone, OR: OAuth2Repository + Clone> OAuth2UseCa
         oauth2_client: BasicClient,
         members_repository: MR,
         oauth2_repository: OR,
+        csrf_token_ttl: Duration,
+        guild_id: u64,
+        required_role_ids: Vec<u64>,
+        audit_log: AuditLogUseCase<MR>,
     ) -> Self {
         Self {
             oauth2_client,
             members_repository,
             oauth2_repository,
+            csrf_token_ttl,
+            guild_id,
+            required_role_ids,
+            refresh_locks: Arc::default(),
+            audit_log,
         }
     }
 
-    /// Returns auth-url.
+    /// Returns the url of `/oauth2/discord`, which is served next to the callback.
+    pub(crate) fn authorization_entry_url(&self) -> Option<String> {
+        let redirect_url = self.oauth2_client.redirect_url()?;
+
+        redirect_url
+            .url()
+            .as_str()
+            .strip_suffix("/callback")
+            .map(str::to_owned)
+    }
+
+    /// Returns auth-url. When `requested_by` is given, only that discord user can complete it.
     #[tracing::instrument(skip(self))]
-    pub(crate) async fn authenticate(&self) -> anyhow::Result<String> {
+    pub(crate) async fn authenticate(
+        &self,
+        requested_by: Option<String>,
+    ) -> anyhow::Result<String> {
         let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
         let (auth_url, csrf_token) = self
@@ -50,6 +150,8 @@ impl<MR: MemberDataRepository + Clone, OR: OAuth2Repository + Clone> OAuth2UseCa
             .save_csrf_token(
                 csrf_token.secret().to_owned(),
                 pkce_verifier.secret().to_owned(),
+                Utc::now() + self.csrf_token_ttl,
+                requested_by,
             )
             .await
             .context("could not save csrf-token and pkce-verifier")
@@ -58,20 +160,32 @@ impl<MR: MemberDataRepository + Clone, OR: OAuth2Repository + Clone> OAuth2UseCa
         Ok(auth_url.to_string())
     }
 
+    /// Returns the discord user id of the member who authorized.
     #[tracing::instrument(skip(self, csrf_token, code))]
     pub(crate) async fn get_token_data(
         &self,
         csrf_token: String,
         code: String,
-    ) -> anyhow::Result<()> {
-        let token_data = self
-            .oauth2_repository
-            .delete_csrf_token(csrf_token)
-            .await
-            .context("could not get csrf-token from database")
-            .inspect_err(|err| tracing::error!("{}", err))?;
+    ) -> anyhow::Result<String> {
+        let token_data = match self.oauth2_repository.delete_csrf_token(csrf_token).await {
+            Err(RepositoryError::NotFound { .. }) => {
+                tracing::info!("unknown csrf-token was given");
+                return Err(OAuth2Error::UnknownCsrfToken.into());
+            }
+            result => result
+                .context("could not get csrf-token from database")
+                .inspect_err(|err| tracing::error!("{}", err))?,
+        };
         tracing::info!("fetched csrf-token data from database");
 
+        if token_data.expires_at <= Utc::now() {
+            tracing::info!(
+                "expired csrf-token was given: expiresAt: {}",
+                token_data.expires_at
+            );
+            return Err(OAuth2Error::CsrfTokenExpired.into());
+        }
+
         let pkce_verifier = PkceCodeVerifier::new(token_data.pkce_verifier);
 
         let token = self
@@ -89,25 +203,117 @@ impl<MR: MemberDataRepository + Clone, OR: OAuth2Repository + Clone> OAuth2UseCa
             .context("could not get current user info")
             .inspect_err(|err| tracing::error!("{}", err))?;
 
+        let verified = match token_data.requested_by {
+            Some(requested_by) if requested_by != user.id.to_string() => {
+                tracing::warn!(
+                    "auth-url was completed by another user: requestedBy: {}, userId: {}",
+                    requested_by,
+                    user.id
+                );
+                Err(OAuth2Error::UnexpectedUser.into())
+            }
+            _ => self.verify_guild_membership(token.access_token()).await,
+        };
+        if let Err(err) = verified {
+            // the token is never stored, so nothing should be able to use it.
+            if let Some(refresh_token) = token.refresh_token() {
+                if let Err(revoke_err) = self.revoke_token(refresh_token.secret().to_owned()).await
+                {
+                    tracing::warn!("could not revoke token of rejected user: {:#}", revoke_err);
+                }
+            }
+            return Err(err);
+        }
+
+        let discord_user_id = user.id.to_string();
+        let before = self.audit_log.capture(&discord_user_id).await;
         self.members_repository
-            .save_oauth2_token(
-                user.id.to_string(),
-                token.access_token().secret().to_owned(),
-                token
-                    .refresh_token()
-                    .context("refresh token is not provided by discord oauth2 server")
-                    .inspect_err(|err| tracing::error!("{}", err))?
-                    .secret()
-                    .to_owned(),
-            )
+            .save_oauth2_token(discord_user_id.clone(), oauth2_data(&token)?)
             .await
             .context("could not save oauth2 token to database")
             .inspect_err(|err| tracing::error!("{}", err))?;
+        // the membership was just verified, even if the bot missed the member joining again.
+        // members a moderator deactivated stay inactive until a moderator reactivates them.
+        let member = self
+            .members_repository
+            .get_member(&discord_user_id)
+            .await
+            .context("could not get member data from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+        if member.deactivated_by.is_none() {
+            self.members_repository
+                .save_inactive_since(discord_user_id.clone(), None, None)
+                .await
+                .context("could not save member activity to database")
+                .inspect_err(|err| tracing::error!("{}", err))?;
+        }
+
+        let after = self.audit_log.capture(&discord_user_id).await;
+        self.audit_log
+            .record(
+                &discord_user_id,
+                AuditAction::Registered,
+                &Actor::Member(discord_user_id.clone()),
+                before,
+                after,
+            )
+            .await;
+
+        Ok(discord_user_id)
+    }
+
+    /// Checks that the user who owns the access-token is in the guild and has one of the required roles.
+    async fn verify_guild_membership(&self, access_token: &AccessToken) -> anyhow::Result<()> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "https://discord.com/api/v10/users/@me/guilds/{}/member",
+                self.guild_id
+            ))
+            .bearer_auth(access_token.secret())
+            .send()
+            .await
+            .context("could not fetch guild membership from discord")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            tracing::info!("user is not a member of the guild");
+            return Err(OAuth2Error::NotGuildMember.into());
+        }
+        let member: CurrentUserGuildMember = response
+            .error_for_status()
+            .context("could not fetch guild membership from discord")
+            .inspect_err(|err| tracing::error!("{}", err))?
+            .json()
+            .await
+            .context("could not parse guild membership from discord")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+
+        let has_required_role = self.required_role_ids.is_empty()
+            || member
+                .roles
+                .iter()
+                .filter_map(|role_id| role_id.parse::<u64>().ok())
+                .any(|role_id| self.required_role_ids.contains(&role_id));
+        if !has_required_role {
+            tracing::info!("member does not have any of the required roles");
+            return Err(OAuth2Error::MissingRequiredRole.into());
+        }
+
         Ok(())
     }
 
+    /// Returns an access-token of the member, refreshing it only when it is about to expire.
+    ///
+    /// Discord rotates the refresh-token on every grant, so refreshes of the same member are
+    /// serialized and the ones which waited reuse the token that was just issued.
+    ///
+    /// Members whose authorization was revoked fail with [`OAuth2Error::AuthorizationRevoked`]
+    /// without asking discord again, until they link through OAuth2 again.
     #[tracing::instrument(skip(self))]
-    pub(crate) async fn refresh_token(&self, discord_user_id: &str) -> anyhow::Result<AccessToken> {
+    pub(crate) async fn get_access_token(
+        &self,
+        discord_user_id: &str,
+    ) -> anyhow::Result<AccessToken> {
         let member = self
             .members_repository
             .get_member(discord_user_id)
@@ -116,34 +322,398 @@ impl<MR: MemberDataRepository + Clone, OR: OAuth2Repository + Clone> OAuth2UseCa
             .inspect_err(|err| tracing::error!("{}", err))?;
         tracing::info!("fetched member data from database");
 
-        let token = self
+        if let Some(since) = member.needs_reauth_since {
+            return Err(OAuth2Error::AuthorizationRevoked { since }.into());
+        }
+        if let Some(access_token) = cached_access_token(&member.oauth2) {
+            return Ok(access_token);
+        }
+
+        let _guard = self.refresh_lock(discord_user_id).await;
+
+        let latest = self
+            .members_repository
+            .get_member(discord_user_id)
+            .await
+            .context("could not get member data from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+
+        if let Some(since) = latest.needs_reauth_since {
+            return Err(OAuth2Error::AuthorizationRevoked { since }.into());
+        }
+        if let Some(access_token) = cached_access_token(&latest.oauth2) {
+            tracing::info!("access-token was refreshed by another task");
+            return Ok(access_token);
+        }
+
+        self.refresh_token(discord_user_id, latest.oauth2.refresh_token, &Actor::System)
+            .await
+    }
+
+    async fn refresh_lock(&self, discord_user_id: &str) -> RefreshGuard {
+        let lock = {
+            let mut locks = self
+                .refresh_locks
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            Arc::clone(locks.entry(discord_user_id.to_owned()).or_default())
+        };
+
+        RefreshGuard {
+            locks: Arc::clone(&self.refresh_locks),
+            discord_user_id: discord_user_id.to_owned(),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    async fn refresh_token(
+        &self,
+        discord_user_id: &str,
+        refresh_token: String,
+        actor: &Actor,
+    ) -> anyhow::Result<AccessToken> {
+        let before = self.audit_log.capture(discord_user_id).await;
+
+        let token = match self
             .oauth2_client
-            .exchange_refresh_token(&RefreshToken::new(member.oauth2.refresh_token))
+            .exchange_refresh_token(&RefreshToken::new(refresh_token))
             .request_async(async_http_client)
             .await
-            .inspect_err(|err| {
+        {
+            Ok(token) => token,
+            // discord answers `invalid_grant` once the member revoked the application,
+            // and the refresh-token never becomes valid again.
+            Err(RequestTokenError::ServerResponse(response))
+                if *response.error() == BasicErrorResponseType::InvalidGrant =>
+            {
+                let since = Utc::now();
+                tracing::warn!(
+                    "refresh-token was rejected, marking the member as needing re-authorization"
+                );
+                self.members_repository
+                    .mark_needs_reauth(discord_user_id.to_owned(), since)
+                    .await
+                    .context("could not save re-authorization status to database")
+                    .inspect_err(|err| tracing::error!("{}", err))?;
+
+                return Err(OAuth2Error::AuthorizationRevoked { since }.into());
+            }
+            Err(err) => {
                 tracing::error!(
                     "could not refresh access-token from discord oauth2 server: {}",
                     err
-                )
-            })?;
+                );
+                return Err(err.into());
+            }
+        };
         tracing::info!("refreshed token from discord oauth2 server");
 
         self.members_repository
-            .save_oauth2_token(
-                discord_user_id.to_owned(),
-                token.access_token().secret().to_owned(),
-                token
-                    .refresh_token()
-                    .context("refresh token is not provided by discord oauth2 server")
-                    .inspect_err(|err| tracing::error!("{}", err))?
-                    .secret()
-                    .to_owned(),
-            )
+            .save_oauth2_token(discord_user_id.to_owned(), oauth2_data(&token)?)
             .await
             .context("could not save oauth2 token to database")
             .inspect_err(|err| tracing::error!("{}", err))?;
 
+        let after = self.audit_log.capture(discord_user_id).await;
+        self.audit_log
+            .record(
+                discord_user_id,
+                AuditAction::TokenRefreshed,
+                actor,
+                before,
+                after,
+            )
+            .await;
+
         Ok(token.access_token().to_owned())
     }
+
+    /// Refreshes the access-token even if it has not expired yet.
+    /// Returns `false` when the member is not registered.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn force_refresh_token(
+        &self,
+        discord_user_id: &str,
+        actor: &Actor,
+    ) -> anyhow::Result<bool> {
+        let _guard = self.refresh_lock(discord_user_id).await;
+
+        let member = match self.members_repository.get_member(discord_user_id).await {
+            Err(RepositoryError::NotFound { .. }) => return Ok(false),
+            result => result
+                .context("could not get member data from database")
+                .inspect_err(|err| tracing::error!("{}", err))?,
+        };
+        if let Some(since) = member.needs_reauth_since {
+            return Err(OAuth2Error::AuthorizationRevoked { since }.into());
+        }
+
+        self.refresh_token(discord_user_id, member.oauth2.refresh_token, actor)
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Refreshes the access-token of every member, carrying on when some of them fail.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn refresh_all_tokens(
+        &self,
+        actor: &Actor,
+    ) -> anyhow::Result<RefreshAllSummary> {
+        let members = self
+            .members_repository
+            .get_all_members()
+            .await
+            .context("could not get members data from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+
+        let mut summary = RefreshAllSummary::default();
+        for member in members {
+            if member.needs_reauth_since.is_some() {
+                summary.skipped += 1;
+                continue;
+            }
+
+            match self
+                .force_refresh_token(&member.discord_user_id, actor)
+                .await
+            {
+                Ok(_) => summary.refreshed += 1,
+                Err(err) => {
+                    tracing::warn!(
+                        "could not refresh token: userId: {}: {:#}",
+                        member.discord_user_id,
+                        err
+                    );
+                    summary.failed += 1;
+                }
+            }
+        }
+        tracing::info!(
+            "refreshed tokens of {} members, skipped {} and failed {}",
+            summary.refreshed,
+            summary.skipped,
+            summary.failed
+        );
+
+        Ok(summary)
+    }
+
+    /// Returns the discord user id of the member who owns the access-token.
+    #[tracing::instrument(skip(self, access_token))]
+    pub(crate) async fn get_token_owner(&self, access_token: &str) -> anyhow::Result<String> {
+        let http = Http::new(&format!("Bearer {}", access_token));
+        let user = http.get_current_user().await.map_err(|err| {
+            tracing::info!(
+                "could not get current user with given access-token: {}",
+                err
+            );
+            OAuth2Error::InvalidAccessToken
+        })?;
+
+        Ok(user.id.to_string())
+    }
+
+    /// Revokes the authorization at discord and deletes every data of the member.
+    /// Returns `false` when the member is not registered.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn delete_member(
+        &self,
+        discord_user_id: &str,
+        actor: &Actor,
+    ) -> anyhow::Result<bool> {
+        // holding the lock keeps a refresh from rotating the token which is being revoked.
+        let _guard = self.refresh_lock(discord_user_id).await;
+
+        let member = match self.members_repository.get_member(discord_user_id).await {
+            Err(RepositoryError::NotFound { .. }) => return Ok(false),
+            result => result
+                .context("could not get member data from database")
+                .inspect_err(|err| tracing::error!("{}", err))?,
+        };
+        let before = AuditedMemberData::from(&member);
+
+        // tokens of revoked authorizations are already invalidated by discord.
+        if member.needs_reauth_since.is_none() {
+            if let Err(err) = self.revoke_token(member.oauth2.refresh_token).await {
+                tracing::warn!(
+                    "could not revoke token, deleting the member data anyway: {:#}",
+                    err
+                );
+            }
+        }
+
+        self.members_repository
+            .delete_member(discord_user_id)
+            .await
+            .context("could not delete member data from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+        tracing::info!("deleted member data");
+
+        self.audit_log
+            .record(
+                discord_user_id,
+                AuditAction::Deleted,
+                actor,
+                Some(before),
+                None,
+            )
+            .await;
+
+        Ok(true)
+    }
+
+    /// Revoking the refresh-token invalidates the whole grant including its access-tokens.
+    async fn revoke_token(&self, refresh_token: String) -> anyhow::Result<()> {
+        self.oauth2_client
+            .revoke_token(StandardRevocableToken::RefreshToken(RefreshToken::new(
+                refresh_token,
+            )))
+            .context("revocation-url is not configured")?
+            .request_async(async_http_client)
+            .await
+            .context("could not revoke token at discord oauth2 server")?;
+        tracing::info!("revoked token at discord oauth2 server");
+
+        Ok(())
+    }
+
+    /// Writes every stored token again, so that they get encrypted with the current primary key.
+    ///
+    /// Only the token columns are rewritten, and only while they still hold what was read,
+    /// since a token refreshed meanwhile is already encrypted with the primary key.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn reencrypt_tokens(&self) -> anyhow::Result<usize> {
+        let members = self
+            .members_repository
+            .get_all_members()
+            .await
+            .context("could not get members data from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+
+        let mut reencrypted: usize = 0;
+        for member in members {
+            let replaced = self
+                .members_repository
+                .replace_oauth2_tokens(
+                    member.discord_user_id.clone(),
+                    member.oauth2.clone(),
+                    member.oauth2,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "could not save re-encrypted token: userId: {}",
+                        member.discord_user_id
+                    )
+                })
+                .inspect_err(|err| tracing::error!("{}", err))?;
+
+            if replaced {
+                reencrypted = reencrypted.saturating_add(1);
+            } else {
+                tracing::info!(
+                    "skipped member whose token changed meanwhile: userId: {}",
+                    member.discord_user_id
+                );
+            }
+        }
+        tracing::info!("re-encrypted tokens of {} members", reencrypted);
+
+        Ok(reencrypted)
+    }
+
+    /// Deletes csrf-tokens of authorizations which were never completed.
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn sweep_expired_csrf_tokens(&self) -> anyhow::Result<u64> {
+        let deleted = self
+            .oauth2_repository
+            .delete_expired_csrf_tokens(Utc::now())
+            .await
+            .context("could not delete expired csrf-tokens from database")
+            .inspect_err(|err| tracing::error!("{}", err))?;
+        tracing::info!("deleted {} expired csrf-tokens", deleted);
+
+        Ok(deleted)
+    }
+}
+
+fn oauth2_data(token: &BasicTokenResponse) -> anyhow::Result<MemberOAuth2Data> {
+    let refresh_token = token
+        .refresh_token()
+        .context("refresh token is not provided by discord oauth2 server")
+        .inspect_err(|err| tracing::error!("{}", err))?;
+
+    Ok(MemberOAuth2Data {
+        access_token: token.access_token().secret().to_owned(),
+        refresh_token: refresh_token.secret().to_owned(),
+        expires_at: token
+            .expires_in()
+            .and_then(|expires_in| Duration::from_std(expires_in).ok())
+            .map(|expires_in| Utc::now() + expires_in),
+    })
+}
+
+fn cached_access_token(oauth2: &MemberOAuth2Data) -> Option<AccessToken> {
+    let expires_at = oauth2.expires_at?;
+
+    (expires_at - Duration::seconds(ACCESS_TOKEN_EXPIRY_MARGIN_SECS) > Utc::now())
+        .then(|| AccessToken::new(oauth2.access_token.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Duration;
+    use oauth2::basic::BasicClient;
+    use oauth2::{AuthUrl, ClientId};
+    use serenity::http::Http;
+
+    use crate::infra::repository::memory::{MemberDataRepositoryImpl, OAuth2RepositoryImpl};
+    use crate::usecase::audit::AuditLogUseCase;
+
+    use super::OAuth2UseCase;
+
+    #[tokio::test]
+    async fn refresh_locks_are_dropped_with_their_last_holder() -> anyhow::Result<()> {
+        let members_repository = MemberDataRepositoryImpl::new();
+        let usecase = OAuth2UseCase::new(
+            BasicClient::new(
+                ClientId::new("client".to_owned()),
+                None,
+                AuthUrl::new("https://discord.com/oauth2/authorize".to_owned())?,
+                None,
+            ),
+            members_repository.clone(),
+            OAuth2RepositoryImpl::new(),
+            Duration::minutes(10),
+            0,
+            Vec::new(),
+            AuditLogUseCase::new(members_repository, Arc::new(Http::new("")), None),
+        );
+
+        let first = usecase.refresh_lock("100").await;
+        let second = tokio::spawn({
+            let usecase = usecase.clone();
+            async move {
+                drop(usecase.refresh_lock("100").await);
+            }
+        });
+        drop(usecase.refresh_lock("200").await);
+        assert_eq!(
+            usecase.refresh_locks.lock().map(|locks| locks.len()).ok(),
+            Some(1)
+        );
+
+        // gone only once the holder waiting for it is done as well.
+        drop(first);
+        second.await?;
+        assert_eq!(
+            usecase.refresh_locks.lock().map(|locks| locks.len()).ok(),
+            Some(0)
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use crate::config::StorageConfig;
+use crate::infra::repository::sqlite::{self, MemberDataRepositoryImpl, OAuth2RepositoryImpl};
+use crate::infra::repository::{DynMemberDataRepository, DynOAuth2Repository};
+
+use super::Repositories;
+
+pub(crate) async fn get_sqlite_repositories(
+    config: &StorageConfig,
+) -> anyhow::Result<Repositories> {
+    let pool = sqlite::connect(&config.sqlite_database_url)
+        .await
+        .inspect_err(|err| tracing::error!("{:#}", err))?;
+
+    let members_repository: DynMemberDataRepository =
+        Arc::new(MemberDataRepositoryImpl::new(pool.clone()));
+    let oauth2_repository: DynOAuth2Repository = Arc::new(OAuth2RepositoryImpl::new(pool));
+
+    Ok((members_repository, oauth2_repository))
+}